@@ -0,0 +1,84 @@
+//! Converts the CSV fixtures like `CsvToExcel` does and prints how many rows per second are written.
+//!
+//! Run it with `cargo bench`. It uses `test/100k.csv` and `test/1m.csv` when they exist, otherwise it generates them in `target/fixtures`.
+
+use fastexcel::excel::{Cell, Row, WorkBook};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Result as IoResult, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+const FIXTURES: [(&str, usize); 2] = [("100k", 100_000), ("1m", 1_000_000)];
+
+fn main() -> IoResult<()> {
+    for (name, rows) in FIXTURES.iter() {
+        let csv_path = fixture(name, *rows)?;
+        let xlsx_path = std::env::temp_dir().join(format!("fastexcel-bench-{}.xlsx", name));
+        let start = Instant::now();
+        let written = convert(&csv_path, &xlsx_path)?;
+        let elapsed = start.elapsed();
+        println!(
+            "{:>4}: {} rows in {:.2?}, {:.0} rows/sec, {} bytes",
+            name,
+            written,
+            elapsed,
+            written as f64 / elapsed.as_secs_f64(),
+            fs::metadata(&xlsx_path)?.len()
+        );
+        fs::remove_file(&xlsx_path)?;
+    }
+    Ok(())
+}
+
+/// Writes the CSV file into a XLSX file as `CsvToExcel` does, returning the number of rows.
+fn convert(csv_path: &PathBuf, xlsx_path: &PathBuf) -> IoResult<usize> {
+    let mut file = File::create(xlsx_path)?;
+    let mut workbook = WorkBook::new(&mut file)?;
+    let rows = workbook.get_new_sheet().write_sheet(|sheet_writer| {
+        let mut rows = 0;
+        for line in BufReader::new(File::open(csv_path)?).lines() {
+            let line = line?;
+            let mut row = Row::new();
+            for column in line.split(',') {
+                row.add_cell(Cell::from(column));
+            }
+            sheet_writer.write_row(row)?;
+            rows += 1;
+        }
+        Ok(rows)
+    })?;
+    workbook.finish()?;
+    file.flush()?;
+    Ok(rows)
+}
+
+/// The path of a fixture with a header and the given number of rows, generated the first time.
+fn fixture(name: &str, rows: usize) -> IoResult<PathBuf> {
+    let path = PathBuf::from(format!("test/{}.csv", name));
+    if path.exists() {
+        return Ok(path);
+    }
+    let path = PathBuf::from(format!("target/fixtures/{}.csv", name));
+    if path.exists() {
+        return Ok(path);
+    }
+    fs::create_dir_all("target/fixtures")?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    writeln!(writer, "No,Name,Gender,Email,Amount,Date")?;
+    for i in 1..=rows {
+        writeln!(
+            writer,
+            "{},Name {},{},user{}@example.com,{}.{:02},2023-{:02}-{:02}",
+            i,
+            i,
+            if i % 2 == 0 { "Male" } else { "Female" },
+            i,
+            i % 10_000,
+            i % 100,
+            i % 12 + 1,
+            i % 28 + 1
+        )?;
+    }
+    writer.flush()?;
+    Ok(path)
+}
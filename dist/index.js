@@ -17,8 +17,8 @@ class CsvFileWriter {
 }
 
 class Converter {
-  static async toXLSX(srcCsv, xlsFilePath) {
-    return await convertCsvToExcel(srcCsv, xlsFilePath);
+  static async toXLSX(srcCsv, xlsFilePath, options = {}) {
+    return await convertCsvToExcel(srcCsv, xlsFilePath, options);
   }
 }
 
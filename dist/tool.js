@@ -1,7 +1,16 @@
 const lib = require("../index.node");
 
-const convertCsvToExcel = (csvSrc, xlsDst) => {
-  return lib.CsvToExcel(csvSrc, xlsDst);
+/**
+ * @param {string} csvSrc
+ * @param {string} xlsDst
+ * @param {{ password?: string, compression?: 'stored' | 'deflated', compressionLevel?: number, compressionThreads?: number, metadataFirst?: boolean }} [options]
+ * `password` encrypts the XLSX file, so Excel asks for it to open it.
+ * `compression` and `compressionLevel` (0 to 9) set how the parts of the XLSX file are compressed.
+ * `compressionThreads` deflates the sheet with several threads.
+ * `metadataFirst` writes the workbook and styles before the sheet, for forward-only zip readers.
+ */
+const convertCsvToExcel = (csvSrc, xlsDst, options = {}) => {
+  return lib.CsvToExcel(csvSrc, xlsDst, options);
 };
 
 module.exports = {
@@ -0,0 +1,308 @@
+use crate::excel::row::{absolute_range, escape_xml};
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The kind of chart to draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChartType {
+    Line,
+    /// Horizontal bars.
+    Bar,
+    /// Vertical bars.
+    Column,
+    Pie,
+    Scatter,
+}
+
+/// Where the legend of a chart is placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LegendPosition {
+    Right,
+    Left,
+    Top,
+    Bottom,
+}
+
+/// A native Excel chart, drawn from ranges of any sheet of the workbook. Insert it with [Sheet::insert_chart](crate::excel::Sheet::insert_chart) or [SheetWriter::insert_chart](crate::excel::SheetWriter::insert_chart).
+#[derive(Clone, Debug)]
+pub struct Chart {
+    chart_type: ChartType,
+    title: Option<String>,
+    x_axis_title: Option<String>,
+    y_axis_title: Option<String>,
+    legend: Option<LegendPosition>,
+    series: Vec<ChartSeries>,
+    width: u32,
+    height: u32,
+}
+
+/// A series of a [Chart](Chart). The values and the categories are ranges, like `B2:B31`, of the sheet with the given name.
+#[derive(Clone, Debug)]
+pub struct ChartSeries {
+    sheet_name: String,
+    values: String,
+    categories: Option<String>,
+    name: Option<String>,
+}
+
+impl ChartSeries {
+    pub fn new(sheet_name: impl Into<String>, values: impl Into<String>) -> Self {
+        Self {
+            sheet_name: sheet_name.into(),
+            values: values.into(),
+            categories: None,
+            name: None,
+        }
+    }
+
+    /// The range with the categories, or the x values of a scatter chart, in the same sheet as the values.
+    pub fn categories(mut self, categories: impl Into<String>) -> Self {
+        self.categories = Some(categories.into());
+        self
+    }
+
+    /// The name shown in the legend.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+}
+
+impl Chart {
+    /// Creates a chart of 480x288 pixels with the legend on the right.
+    pub fn new(chart_type: ChartType) -> Self {
+        Self {
+            chart_type,
+            title: None,
+            x_axis_title: None,
+            y_axis_title: None,
+            legend: Some(LegendPosition::Right),
+            series: Vec::new(),
+            width: 480,
+            height: 288,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn x_axis_title(mut self, title: impl Into<String>) -> Self {
+        self.x_axis_title = Some(title.into());
+        self
+    }
+
+    pub fn y_axis_title(mut self, title: impl Into<String>) -> Self {
+        self.y_axis_title = Some(title.into());
+        self
+    }
+
+    /// Sets the legend position, or hides it with `None`.
+    pub fn legend(mut self, legend: Option<LegendPosition>) -> Self {
+        self.legend = legend;
+        self
+    }
+
+    /// Sets the size of the chart in pixels.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn add_series(mut self, series: ChartSeries) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Checks the chart has series and their ranges are valid.
+    pub(crate) fn validate(&self) -> IoResult<()> {
+        if self.series.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a chart needs at least one series",
+            ));
+        }
+        for series in self.series.iter() {
+            let ranges = std::iter::once(&series.values).chain(series.categories.iter());
+            for range in ranges {
+                if absolute_range(&series.sheet_name, range).is_none() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid chart range {}", range),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the series only reference sheets of the workbook, once all of them are known.
+    pub(crate) fn check_sheets(&self, sheet_names: &[String]) -> IoResult<()> {
+        for series in self.series.iter() {
+            if !sheet_names.contains(&series.sheet_name) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid chart series: there is no sheet {}", series.sheet_name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<c:chartSpace xmlns:c="http://schemas.openxmlformats.org/drawingml/2006/chart" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+<c:chart>
+"#
+        )?;
+        match &self.title {
+            Some(title) => {
+                write_title(writer, title)?;
+                writeln!(writer, "<c:autoTitleDeleted val=\"0\"/>")?;
+            }
+            None => writeln!(writer, "<c:autoTitleDeleted val=\"1\"/>")?,
+        }
+        writeln!(writer, "<c:plotArea>\n<c:layout/>")?;
+        match self.chart_type {
+            ChartType::Line => writeln!(
+                writer,
+                "<c:lineChart><c:grouping val=\"standard\"/><c:varyColors val=\"0\"/>"
+            )?,
+            ChartType::Bar | ChartType::Column => writeln!(
+                writer,
+                "<c:barChart><c:barDir val=\"{}\"/><c:grouping val=\"clustered\"/><c:varyColors val=\"0\"/>",
+                if self.chart_type == ChartType::Bar { "bar" } else { "col" }
+            )?,
+            ChartType::Pie => writeln!(writer, "<c:pieChart><c:varyColors val=\"1\"/>")?,
+            ChartType::Scatter => writeln!(
+                writer,
+                "<c:scatterChart><c:scatterStyle val=\"lineMarker\"/><c:varyColors val=\"0\"/>"
+            )?,
+        }
+        for (i, series) in self.series.iter().enumerate() {
+            self.write_series(writer, i, series)?;
+        }
+        match self.chart_type {
+            ChartType::Line => writeln!(
+                writer,
+                "<c:marker val=\"1\"/><c:axId val=\"{}\"/><c:axId val=\"{}\"/></c:lineChart>",
+                X_AXIS_ID, Y_AXIS_ID
+            )?,
+            ChartType::Bar | ChartType::Column => writeln!(
+                writer,
+                "<c:gapWidth val=\"150\"/><c:axId val=\"{}\"/><c:axId val=\"{}\"/></c:barChart>",
+                X_AXIS_ID, Y_AXIS_ID
+            )?,
+            ChartType::Pie => writeln!(writer, "<c:firstSliceAng val=\"0\"/></c:pieChart>")?,
+            ChartType::Scatter => writeln!(
+                writer,
+                "<c:axId val=\"{}\"/><c:axId val=\"{}\"/></c:scatterChart>",
+                X_AXIS_ID, Y_AXIS_ID
+            )?,
+        }
+        self.write_axes(writer)?;
+        writeln!(writer, "</c:plotArea>")?;
+        if let Some(position) = self.legend {
+            let position = match position {
+                LegendPosition::Right => "r",
+                LegendPosition::Left => "l",
+                LegendPosition::Top => "t",
+                LegendPosition::Bottom => "b",
+            };
+            writeln!(
+                writer,
+                "<c:legend><c:legendPos val=\"{}\"/><c:overlay val=\"0\"/></c:legend>",
+                position
+            )?;
+        }
+        write!(writer, "<c:plotVisOnly val=\"1\"/>\n</c:chart>\n</c:chartSpace>")
+    }
+
+    fn write_series(
+        &self,
+        writer: &mut impl Write,
+        index: usize,
+        series: &ChartSeries,
+    ) -> IoResult<()> {
+        write!(
+            writer,
+            "<c:ser><c:idx val=\"{}\"/><c:order val=\"{}\"/>",
+            index, index
+        )?;
+        if let Some(name) = &series.name {
+            write!(writer, "<c:tx><c:v>{}</c:v></c:tx>", escape_xml(name))?;
+        }
+        let values = reference(series, &series.values);
+        let categories = series
+            .categories
+            .as_ref()
+            .map(|categories| reference(series, categories));
+        match self.chart_type {
+            ChartType::Scatter => {
+                if let Some(categories) = categories {
+                    write!(writer, "<c:xVal><c:numRef><c:f>{}</c:f></c:numRef></c:xVal>", categories)?;
+                }
+                write!(writer, "<c:yVal><c:numRef><c:f>{}</c:f></c:numRef></c:yVal><c:smooth val=\"0\"/>", values)?;
+            }
+            _ => {
+                if let Some(categories) = categories {
+                    write!(writer, "<c:cat><c:strRef><c:f>{}</c:f></c:strRef></c:cat>", categories)?;
+                }
+                write!(writer, "<c:val><c:numRef><c:f>{}</c:f></c:numRef></c:val>", values)?;
+                if self.chart_type == ChartType::Line {
+                    write!(writer, "<c:smooth val=\"0\"/>")?;
+                }
+            }
+        }
+        writeln!(writer, "</c:ser>")
+    }
+
+    fn write_axes(&self, writer: &mut impl Write) -> IoResult<()> {
+        let (x_position, y_position) = match self.chart_type {
+            ChartType::Pie => return Ok(()),
+            ChartType::Bar => ("l", "b"),
+            _ => ("b", "l"),
+        };
+        if self.chart_type == ChartType::Scatter {
+            write!(writer, "<c:valAx><c:axId val=\"{}\"/><c:scaling><c:orientation val=\"minMax\"/></c:scaling><c:delete val=\"0\"/><c:axPos val=\"{}\"/>", X_AXIS_ID, x_position)?;
+        } else {
+            write!(writer, "<c:catAx><c:axId val=\"{}\"/><c:scaling><c:orientation val=\"minMax\"/></c:scaling><c:delete val=\"0\"/><c:axPos val=\"{}\"/>", X_AXIS_ID, x_position)?;
+        }
+        if let Some(title) = &self.x_axis_title {
+            write_title(writer, title)?;
+        }
+        write!(writer, "<c:numFmt formatCode=\"General\" sourceLinked=\"1\"/><c:tickLblPos val=\"nextTo\"/><c:crossAx val=\"{}\"/><c:crosses val=\"autoZero\"/>", Y_AXIS_ID)?;
+        if self.chart_type == ChartType::Scatter {
+            writeln!(writer, "<c:crossBetween val=\"midCat\"/></c:valAx>")?;
+        } else {
+            writeln!(writer, "<c:auto val=\"1\"/><c:lblAlgn val=\"ctr\"/><c:lblOffset val=\"100\"/></c:catAx>")?;
+        }
+        write!(writer, "<c:valAx><c:axId val=\"{}\"/><c:scaling><c:orientation val=\"minMax\"/></c:scaling><c:delete val=\"0\"/><c:axPos val=\"{}\"/><c:majorGridlines/>", Y_AXIS_ID, y_position)?;
+        if let Some(title) = &self.y_axis_title {
+            write_title(writer, title)?;
+        }
+        writeln!(writer, "<c:numFmt formatCode=\"General\" sourceLinked=\"1\"/><c:tickLblPos val=\"nextTo\"/><c:crossAx val=\"{}\"/><c:crosses val=\"autoZero\"/><c:crossBetween val=\"between\"/></c:valAx>", X_AXIS_ID)
+    }
+}
+
+const X_AXIS_ID: u32 = 50010001;
+const Y_AXIS_ID: u32 = 50010002;
+
+fn reference(series: &ChartSeries, range: &str) -> String {
+    escape_xml(&absolute_range(&series.sheet_name, range).unwrap_or_default())
+}
+
+fn write_title(writer: &mut impl Write, title: &str) -> IoResult<()> {
+    write!(
+        writer,
+        "<c:title><c:tx><c:rich><a:bodyPr/><a:p><a:r><a:t>{}</a:t></a:r></a:p></c:rich></c:tx><c:overlay val=\"0\"/></c:title>",
+        escape_xml(title)
+    )
+}
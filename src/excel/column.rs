@@ -0,0 +1,109 @@
+use crate::excel::row::{parse_column_range, MAX_OUTLINE_LEVEL};
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The width Excel uses for the columns of the default font, in characters.
+const DEFAULT_COLUMN_WIDTH: f64 = 9.140625;
+
+/// The properties of one or more columns. Set them with [Sheet::set_columns](crate::excel::Sheet::set_columns).
+///
+/// Columns with an outline level are grouped next to their summary column, so Excel can expand and collapse them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Column {
+    hidden: bool,
+    outline_level: u8,
+    collapsed: bool,
+}
+
+impl Column {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Puts the columns in an outline group, from 1 to 7.
+    pub fn outline_level(mut self, outline_level: u8) -> Self {
+        self.outline_level = outline_level;
+        self
+    }
+
+    /// Marks the column as the summary column of a collapsed group. The columns of the group should also be hidden.
+    pub fn collapsed(mut self, collapsed: bool) -> Self {
+        self.collapsed = collapsed;
+        self
+    }
+}
+
+/// The columns of a sheet with their own properties, written as `<cols>`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Columns {
+    columns: BTreeMap<usize, Column>,
+}
+
+impl Columns {
+    /// Sets the properties of a range of columns like `B:D`, replacing the ones they had.
+    pub(crate) fn set(&mut self, columns: &str, column: Column) -> IoResult<()> {
+        if column.outline_level > MAX_OUTLINE_LEVEL {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the outline level can't be greater than {}", MAX_OUTLINE_LEVEL),
+            ));
+        }
+        let (first, last) = parse_column_range(columns)?;
+        for index in first..=last {
+            self.columns.insert(index, column.clone());
+        }
+        Ok(())
+    }
+
+    /// The deepest outline level of the columns.
+    pub(crate) fn outline_levels(&self) -> u8 {
+        self.columns
+            .values()
+            .map(|column| column.outline_level)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Writes the columns, joining the adjacent ones with the same properties in a single `<col>`.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        let mut ranges: Vec<(usize, usize, &Column)> = Vec::new();
+        for (index, column) in self.columns.iter() {
+            match ranges.last_mut() {
+                Some((_, last, previous)) if *last + 1 == *index && *previous == column => {
+                    *last = *index
+                }
+                _ => ranges.push((*index, *index, column)),
+            }
+        }
+        write!(writer, "<cols>")?;
+        for (first, last, column) in ranges {
+            write!(
+                writer,
+                "<col min=\"{}\" max=\"{}\" width=\"{}\"",
+                first + 1,
+                last + 1,
+                DEFAULT_COLUMN_WIDTH
+            )?;
+            if column.hidden {
+                write!(writer, " hidden=\"1\"")?;
+            }
+            if column.outline_level > 0 {
+                write!(writer, " outlineLevel=\"{}\"", column.outline_level)?;
+            }
+            if column.collapsed {
+                write!(writer, " collapsed=\"1\"")?;
+            }
+            write!(writer, "/>")?;
+        }
+        write!(writer, "</cols>")
+    }
+}
@@ -0,0 +1,131 @@
+use crate::excel::row::escape_xml;
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The shape ids of a block of the VML drawings. Every drawing takes whole blocks, as listed in its `o:idmap`.
+const SHAPE_BLOCK_SIZE: usize = 1024;
+
+/// The notes of a sheet, written as `xl/commentsN.xml` plus the legacy VML drawing Excel uses to show them.
+#[derive(Default, Debug)]
+pub(crate) struct Comments {
+    authors: Vec<String>,
+    list: Vec<Comment>,
+    /// The cells with a comment, as `(column, row)`, so a second one is rejected without going through the list.
+    cells: HashSet<(usize, usize)>,
+    /// The relationship id of the VML drawing, referenced by the sheet `<legacyDrawing>`.
+    pub(crate) vml_relationship_id: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Comment {
+    cell_ref: String,
+    column_index: usize,
+    row_index: usize,
+    author_id: usize,
+    text: String,
+}
+
+impl Comments {
+    pub(crate) fn add(
+        &mut self,
+        cell_ref: String,
+        column_index: usize,
+        row_index: usize,
+        author: &str,
+        text: &str,
+    ) -> IoResult<()> {
+        if !self.cells.insert((column_index, row_index)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the cell {} already has a comment", cell_ref),
+            ));
+        }
+        let author_id = match self.authors.iter().position(|a| a == author) {
+            Some(id) => id,
+            None => {
+                self.authors.push(author.to_string());
+                self.authors.len() - 1
+            }
+        };
+        self.list.push(Comment {
+            cell_ref,
+            column_index,
+            row_index,
+            author_id,
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    /// How many blocks of shape ids the notes take. The first id of every block is left unused, as Excel does.
+    pub(crate) fn shape_blocks(&self) -> usize {
+        self.list.len().div_ceil(SHAPE_BLOCK_SIZE - 1)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub(crate) fn write_comments(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<comments xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<authors>
+"#
+        )?;
+        for author in self.authors.iter() {
+            writeln!(writer, "<author>{}</author>", escape_xml(author))?;
+        }
+        write!(writer, "</authors>\n<commentList>\n")?;
+        for comment in self.list.iter() {
+            writeln!(
+                writer,
+                "<comment ref=\"{}\" authorId=\"{}\"><text><r><t xml:space=\"preserve\">{}</t></r></text></comment>",
+                comment.cell_ref,
+                comment.author_id,
+                escape_xml(&comment.text)
+            )?;
+        }
+        write!(writer, "</commentList>\n</comments>")
+    }
+
+    /// Writes the note shapes, with the ids of the [blocks](Comments::shape_blocks) starting at `first_block`, so they are unique in the workbook.
+    pub(crate) fn write_vml(&self, first_block: usize, writer: &mut impl Write) -> IoResult<()> {
+        let blocks = (first_block..first_block + self.shape_blocks())
+            .map(|block| block.to_string())
+            .collect::<Vec<String>>();
+        write!(
+            writer,
+            r##"<xml xmlns:v="urn:schemas-microsoft-com:vml" xmlns:o="urn:schemas-microsoft-com:office:office" xmlns:x="urn:schemas-microsoft-com:office:excel">
+<o:shapelayout v:ext="edit"><o:idmap v:ext="edit" data="{}"/></o:shapelayout>
+<v:shapetype id="_x0000_t202" coordsize="21600,21600" o:spt="202" path="m,l,21600r21600,l21600,xe">
+<v:stroke joinstyle="miter"/><v:path gradientshapeok="t" o:connecttype="rect"/>
+</v:shapetype>
+"##,
+            blocks.join(",")
+        )?;
+        for (i, comment) in self.list.iter().enumerate() {
+            let row = comment.row_index - 1;
+            let column = comment.column_index;
+            write!(
+                writer,
+                r##"<v:shape id="_x0000_s{}" type="#_x0000_t202" style="position:absolute;margin-left:59.25pt;margin-top:1.5pt;width:108pt;height:59.25pt;z-index:{};visibility:hidden" fillcolor="#ffffe1" o:insetmode="auto">
+<v:fill color2="#ffffe1"/><v:shadow on="t" color="black" obscured="t"/><v:path o:connecttype="none"/>
+<v:textbox style="mso-direction-alt:auto"><div style="text-align:left"></div></v:textbox>
+<x:ClientData ObjectType="Note"><x:MoveWithCells/><x:SizeWithCells/><x:Anchor>{}, 15, {}, 10, {}, 15, {}, 4</x:Anchor><x:AutoFill>False</x:AutoFill><x:Row>{}</x:Row><x:Column>{}</x:Column></x:ClientData>
+</v:shape>
+"##,
+                (first_block + i / (SHAPE_BLOCK_SIZE - 1)) * SHAPE_BLOCK_SIZE + i % (SHAPE_BLOCK_SIZE - 1) + 1,
+                i + 1,
+                column + 1,
+                row.saturating_sub(1),
+                column + 3,
+                row + 3,
+                row,
+                column
+            )?;
+        }
+        write!(writer, "</xml>")
+    }
+}
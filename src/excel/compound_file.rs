@@ -0,0 +1,350 @@
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The size of the sectors of a version 3 compound file.
+const SECTOR_SIZE: usize = 512;
+/// The longest stream a version 3 compound file can hold.
+pub(crate) const MAX_STREAM_LENGTH: u64 = 0x8000_0000;
+/// The size of the sectors of the mini stream, holding the streams smaller than [MINI_STREAM_CUTOFF].
+const MINI_SECTOR_SIZE: usize = 64;
+const MINI_STREAM_CUTOFF: usize = 4096;
+/// The sector numbers a FAT, mini FAT or DIFAT sector holds.
+const IDS_PER_SECTOR: usize = SECTOR_SIZE / 4;
+/// The FAT sectors listed in the header. The rest are listed in DIFAT sectors.
+const HEADER_DIFAT_LENGTH: usize = 109;
+const DIRECTORY_ENTRY_SIZE: usize = 128;
+
+const DIFAT_SECTOR: u32 = 0xFFFF_FFFC;
+const FAT_SECTOR: u32 = 0xFFFF_FFFD;
+const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+const FREE_SECTOR: u32 = 0xFFFF_FFFF;
+const NO_STREAM: u32 = 0xFFFF_FFFF;
+
+/// Writes an OLE compound file (version 3) with the given streams in its root storage, as `(name, length)`.
+/// The data of every stream is written by `write_stream` with its index, so it doesn't need to be in memory. The streams of 4096 bytes or more are written first, then the smaller ones.
+pub(crate) fn write_compound_file<W: Write>(
+    writer: &mut W,
+    streams: &[(&str, usize)],
+    mut write_stream: impl FnMut(usize, &mut StreamWriter<'_, W>) -> IoResult<()>,
+) -> IoResult<()> {
+    if let Some((name, length)) = streams.iter().find(|(_, length)| *length as u64 > MAX_STREAM_LENGTH) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("the stream {} has {} bytes, more than the {} of a compound file", name, length, MAX_STREAM_LENGTH),
+        ));
+    }
+    // The big streams go first in the sectors, then the mini stream holding the small ones.
+    let mut big_starts = Vec::new();
+    let mut mini_starts = Vec::new();
+    let mut sectors = 0;
+    let mut mini_sectors = 0;
+    for (_, length) in streams.iter() {
+        if *length < MINI_STREAM_CUTOFF {
+            mini_starts.push(Some(mini_sectors));
+            big_starts.push(None);
+            mini_sectors += sector_count(*length, MINI_SECTOR_SIZE);
+        } else {
+            big_starts.push(Some(sectors));
+            mini_starts.push(None);
+            sectors += sector_count(*length, SECTOR_SIZE);
+        }
+    }
+    let mini_stream_size = mini_sectors * MINI_SECTOR_SIZE;
+    let mini_stream_start = sectors;
+    let mini_stream_sectors = sector_count(mini_stream_size, SECTOR_SIZE);
+    let mini_fat_start = mini_stream_start + mini_stream_sectors;
+    let mini_fat_sectors = sector_count(mini_sectors, IDS_PER_SECTOR);
+    let directory_start = mini_fat_start + mini_fat_sectors;
+    let directory_sectors = sector_count((streams.len() + 1) * DIRECTORY_ENTRY_SIZE, SECTOR_SIZE);
+    let fat_start = directory_start + directory_sectors;
+
+    // The FAT also maps its own sectors and the DIFAT ones, so their count depends on itself.
+    let (mut fat_sectors, mut difat_sectors) = (0, 0);
+    loop {
+        let total = fat_start + fat_sectors + difat_sectors;
+        let needed_fat = sector_count(total, IDS_PER_SECTOR);
+        let needed_difat = sector_count(
+            needed_fat.saturating_sub(HEADER_DIFAT_LENGTH),
+            IDS_PER_SECTOR - 1,
+        );
+        if needed_fat == fat_sectors && needed_difat == difat_sectors {
+            break;
+        }
+        fat_sectors = needed_fat;
+        difat_sectors = needed_difat;
+    }
+    let difat_start = fat_start + fat_sectors;
+
+    let mut fat = Vec::with_capacity(fat_sectors * IDS_PER_SECTOR);
+    for (i, (_, length)) in streams.iter().enumerate() {
+        if let Some(start) = big_starts[i] {
+            push_chain(&mut fat, start, sector_count(*length, SECTOR_SIZE));
+        }
+    }
+    push_chain(&mut fat, mini_stream_start, mini_stream_sectors);
+    push_chain(&mut fat, mini_fat_start, mini_fat_sectors);
+    push_chain(&mut fat, directory_start, directory_sectors);
+    fat.extend(std::iter::repeat_n(FAT_SECTOR, fat_sectors));
+    fat.extend(std::iter::repeat_n(DIFAT_SECTOR, difat_sectors));
+    fat.resize(fat_sectors * IDS_PER_SECTOR, FREE_SECTOR);
+
+    let mut mini_fat = Vec::with_capacity(mini_fat_sectors * IDS_PER_SECTOR);
+    for (i, (_, length)) in streams.iter().enumerate() {
+        if let Some(start) = mini_starts[i] {
+            push_chain(
+                &mut mini_fat,
+                start,
+                sector_count(*length, MINI_SECTOR_SIZE),
+            );
+        }
+    }
+    mini_fat.resize(mini_fat_sectors * IDS_PER_SECTOR, FREE_SECTOR);
+
+    // Header
+    let mut header = Vec::with_capacity(SECTOR_SIZE);
+    header.extend_from_slice(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+    header.extend_from_slice(&[0; 16]);
+    header.extend_from_slice(&0x003Eu16.to_le_bytes());
+    header.extend_from_slice(&0x0003u16.to_le_bytes());
+    header.extend_from_slice(&0xFFFEu16.to_le_bytes());
+    header.extend_from_slice(&9u16.to_le_bytes());
+    header.extend_from_slice(&6u16.to_le_bytes());
+    header.extend_from_slice(&[0; 6]);
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(fat_sectors as u32).to_le_bytes());
+    header.extend_from_slice(&(directory_start as u32).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(MINI_STREAM_CUTOFF as u32).to_le_bytes());
+    header.extend_from_slice(&chain_start(mini_fat_start, mini_fat_sectors).to_le_bytes());
+    header.extend_from_slice(&(mini_fat_sectors as u32).to_le_bytes());
+    header.extend_from_slice(&chain_start(difat_start, difat_sectors).to_le_bytes());
+    header.extend_from_slice(&(difat_sectors as u32).to_le_bytes());
+    for i in 0..HEADER_DIFAT_LENGTH {
+        let id = if i < fat_sectors {
+            (fat_start + i) as u32
+        } else {
+            FREE_SECTOR
+        };
+        header.extend_from_slice(&id.to_le_bytes());
+    }
+    writer.write_all(&header)?;
+
+    // Big streams
+    for (i, (name, length)) in streams.iter().enumerate() {
+        if big_starts[i].is_some() {
+            write_stream_data(writer, i, name, *length, &mut write_stream)?;
+            write_padding(writer, *length, SECTOR_SIZE)?;
+        }
+    }
+
+    // Mini stream
+    for (i, (name, length)) in streams.iter().enumerate() {
+        if mini_starts[i].is_some() {
+            write_stream_data(writer, i, name, *length, &mut write_stream)?;
+            write_padding(writer, *length, MINI_SECTOR_SIZE)?;
+        }
+    }
+    write_padding(writer, mini_stream_size, SECTOR_SIZE)?;
+    write_ids(writer, &mini_fat)?;
+
+    // Directory
+    let mut names = (0..streams.len()).collect::<Vec<usize>>();
+    names.sort_by(|a, b| compare_names(streams[*a].0, streams[*b].0));
+    let mut tree = vec![(NO_STREAM, NO_STREAM, true); streams.len()];
+    let root_child = build_tree(&names, &mut tree, 0, max_depth(names.len()));
+    let mut directory = Vec::with_capacity(directory_sectors * SECTOR_SIZE);
+    write_directory_entry(
+        &mut directory,
+        "Root Entry",
+        5,
+        true,
+        (NO_STREAM, NO_STREAM, root_child),
+        chain_start(mini_stream_start, mini_stream_sectors),
+        mini_stream_size,
+    );
+    for (i, (name, length)) in streams.iter().enumerate() {
+        let (left, right, black) = tree[i];
+        let start = match (big_starts[i], mini_starts[i]) {
+            (Some(start), _) | (_, Some(start)) => start as u32,
+            _ => END_OF_CHAIN,
+        };
+        write_directory_entry(
+            &mut directory,
+            name,
+            2,
+            black,
+            (left, right, NO_STREAM),
+            start,
+            *length,
+        );
+    }
+    while directory.len() < directory_sectors * SECTOR_SIZE {
+        write_directory_entry(
+            &mut directory,
+            "",
+            0,
+            false,
+            (NO_STREAM, NO_STREAM, NO_STREAM),
+            0,
+            0,
+        );
+    }
+    writer.write_all(&directory)?;
+
+    // FAT and DIFAT
+    write_ids(writer, &fat)?;
+    let extra_fat = (HEADER_DIFAT_LENGTH..fat_sectors)
+        .map(|i| (fat_start + i) as u32)
+        .collect::<Vec<u32>>();
+    for (i, ids) in extra_fat.chunks(IDS_PER_SECTOR - 1).enumerate() {
+        let mut difat = ids.to_vec();
+        difat.resize(IDS_PER_SECTOR - 1, FREE_SECTOR);
+        difat.push(if i + 1 < difat_sectors {
+            (difat_start + i + 1) as u32
+        } else {
+            END_OF_CHAIN
+        });
+        write_ids(writer, &difat)?;
+    }
+    Ok(())
+}
+
+/// The writer given to write the data of a stream, counting its bytes.
+pub(crate) struct StreamWriter<'a, W> {
+    writer: &'a mut W,
+    written: usize,
+}
+
+impl<W: Write> Write for StreamWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = self.writer.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes the data of a stream, checking it has the length declared in the directory.
+fn write_stream_data<W: Write>(
+    writer: &mut W,
+    index: usize,
+    name: &str,
+    length: usize,
+    write_stream: &mut impl FnMut(usize, &mut StreamWriter<'_, W>) -> IoResult<()>,
+) -> IoResult<()> {
+    let mut stream = StreamWriter { writer, written: 0 };
+    write_stream(index, &mut stream)?;
+    if stream.written != length {
+        return Err(Error::other(format!(
+            "the stream {} has {} bytes instead of {}",
+            name, stream.written, length
+        )));
+    }
+    Ok(())
+}
+
+fn sector_count(size: usize, sector_size: usize) -> usize {
+    size.div_ceil(sector_size)
+}
+
+fn chain_start(start: usize, sectors: usize) -> u32 {
+    if sectors == 0 {
+        END_OF_CHAIN
+    } else {
+        start as u32
+    }
+}
+
+/// Links `count` consecutive sectors starting at `start`.
+fn push_chain(table: &mut Vec<u32>, start: usize, count: usize) {
+    for i in 0..count {
+        table.push(if i + 1 < count {
+            (start + i + 1) as u32
+        } else {
+            END_OF_CHAIN
+        });
+    }
+}
+
+fn write_ids(writer: &mut impl Write, ids: &[u32]) -> IoResult<()> {
+    for id in ids {
+        writer.write_all(&id.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_padding(writer: &mut impl Write, size: usize, sector_size: usize) -> IoResult<()> {
+    let padding = sector_count(size, sector_size) * sector_size - size;
+    writer.write_all(&vec![0; padding])
+}
+
+/// Directory entries are sorted by the length of their names first, then by their uppercase names.
+fn compare_names(a: &str, b: &str) -> std::cmp::Ordering {
+    let a_len = a.encode_utf16().count();
+    let b_len = b.encode_utf16().count();
+    a_len
+        .cmp(&b_len)
+        .then_with(|| a.to_uppercase().cmp(&b.to_uppercase()))
+}
+
+fn max_depth(count: usize) -> usize {
+    let mut depth = 0;
+    while (1 << (depth + 1)) - 1 < count {
+        depth += 1;
+    }
+    depth
+}
+
+/// Builds a balanced red-black tree over the sorted entries, setting `(left, right, black)` of each one. Returns the id of its root.
+/// The nodes in the deepest level are red, so every path has the same number of black nodes.
+fn build_tree(
+    sorted: &[usize],
+    tree: &mut [(u32, u32, bool)],
+    depth: usize,
+    max_depth: usize,
+) -> u32 {
+    if sorted.is_empty() {
+        return NO_STREAM;
+    }
+    let middle = sorted.len() / 2;
+    let index = sorted[middle];
+    let left = build_tree(&sorted[..middle], tree, depth + 1, max_depth);
+    let right = build_tree(&sorted[middle + 1..], tree, depth + 1, max_depth);
+    tree[index] = (left, right, depth == 0 || depth < max_depth);
+    // Entry 0 is the root storage, so the streams start at 1.
+    index as u32 + 1
+}
+
+fn write_directory_entry(
+    directory: &mut Vec<u8>,
+    name: &str,
+    object_type: u8,
+    black: bool,
+    (left, right, child): (u32, u32, u32),
+    start: u32,
+    size: usize,
+) {
+    let mut name_bytes = name
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect::<Vec<u8>>();
+    let name_length = if name.is_empty() {
+        0
+    } else {
+        name_bytes.len() as u16 + 2
+    };
+    name_bytes.resize(64, 0);
+    directory.extend_from_slice(&name_bytes);
+    directory.extend_from_slice(&name_length.to_le_bytes());
+    directory.push(object_type);
+    directory.push(if black { 1 } else { 0 });
+    directory.extend_from_slice(&left.to_le_bytes());
+    directory.extend_from_slice(&right.to_le_bytes());
+    directory.extend_from_slice(&child.to_le_bytes());
+    // CLSID, state bits, creation and modification times
+    directory.extend_from_slice(&[0; 36]);
+    directory.extend_from_slice(&start.to_le_bytes());
+    directory.extend_from_slice(&(size as u64).to_le_bytes());
+}
@@ -0,0 +1,126 @@
+use crate::excel::row::{escape_xml, parse_ref_id};
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The built-in names Excel understands, which only make sense scoped to a sheet.
+const BUILT_IN_NAMES: [&str; 3] = [
+    "_xlnm.Print_Area",
+    "_xlnm.Print_Titles",
+    "_xlnm._FilterDatabase",
+];
+
+/// The names defined in a workbook, written as `<definedNames>` in `workbook.xml`.
+#[derive(Default, Debug)]
+pub(crate) struct DefinedNames {
+    list: Vec<DefinedName>,
+}
+
+#[derive(Debug)]
+struct DefinedName {
+    name: String,
+    formula: String,
+    /// The name of the sheet the name is scoped to. Global when None.
+    sheet_name: Option<String>,
+}
+
+impl DefinedNames {
+    /// Validates and adds a name. The formula is anything Excel accepts after the `=`, like `0.21` or `'Sheet 1'!$A$1:$D$100`.
+    pub(crate) fn add(&mut self, sheet_name: Option<&str>, name: &str, formula: &str) -> IoResult<()> {
+        validate_name(name, sheet_name.is_some())?;
+        let formula = formula.trim_start_matches('=');
+        if formula.is_empty() {
+            return Err(invalid_name(name, "the formula is empty"));
+        }
+        let exists = self.list.iter().any(|defined| {
+            defined.sheet_name.as_deref() == sheet_name && defined.name.eq_ignore_ascii_case(name)
+        });
+        if exists {
+            return Err(invalid_name(name, "it is already defined in this scope"));
+        }
+        self.list.push(DefinedName {
+            name: name.to_string(),
+            formula: formula.to_string(),
+            sheet_name: sheet_name.map(str::to_string),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Writes the names, resolving the sheet scopes into the index of the sheet in `sheet_names`.
+    pub(crate) fn write(&self, writer: &mut impl Write, sheet_names: &[String]) -> IoResult<()> {
+        writeln!(writer, "<definedNames>")?;
+        for defined in self.list.iter() {
+            write!(writer, "<definedName name=\"{}\"", escape_xml(&defined.name))?;
+            if let Some(sheet_name) = &defined.sheet_name {
+                let index = sheet_names
+                    .iter()
+                    .position(|name| name == sheet_name)
+                    .ok_or_else(|| {
+                        invalid_name(&defined.name, &format!("there is no sheet {}", sheet_name))
+                    })?;
+                write!(writer, " localSheetId=\"{}\"", index)?;
+            }
+            if defined.name == "_xlnm._FilterDatabase" {
+                write!(writer, " hidden=\"1\"")?;
+            }
+            writeln!(writer, ">{}</definedName>", escape_xml(&defined.formula))?;
+        }
+        writeln!(writer, "</definedNames>")
+    }
+}
+
+fn invalid_name(name: &str, reason: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("invalid defined name {}: {}", name, reason),
+    )
+}
+
+/// Checks the name follows the Excel rules: it starts with a letter, an underscore or a backslash, it has no spaces and it can't be confused with a cell reference.
+fn validate_name(name: &str, is_sheet_scoped: bool) -> IoResult<()> {
+    if name.starts_with("_xlnm.") {
+        if !BUILT_IN_NAMES.contains(&name) {
+            return Err(invalid_name(name, "unknown built-in name"));
+        }
+        if !is_sheet_scoped {
+            return Err(invalid_name(name, "built-in names must be scoped to a sheet"));
+        }
+        return Ok(());
+    }
+    if name.is_empty() || name.chars().count() > 255 {
+        return Err(invalid_name(name, "it must have between 1 and 255 characters"));
+    }
+    let mut chars = name.chars();
+    let first = chars.next().unwrap_or_default();
+    if !(first.is_alphabetic() || first == '_' || first == '\\') {
+        return Err(invalid_name(
+            name,
+            "it must start with a letter, an underscore or a backslash",
+        ));
+    }
+    if !chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '\\') {
+        return Err(invalid_name(
+            name,
+            "it can only have letters, numbers, underscores, periods and backslashes",
+        ));
+    }
+    if parse_ref_id(name).is_some() || is_r1c1_reference(name) {
+        return Err(invalid_name(name, "it collides with a cell reference"));
+    }
+    Ok(())
+}
+
+/// Names like `R`, `C`, `R1`, `C2` or `R1C2` are references in the R1C1 notation.
+fn is_r1c1_reference(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    let rest = upper.strip_prefix('R').unwrap_or(&upper);
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &rest[digits..];
+    let rest = match rest.strip_prefix('C') {
+        Some(rest) => rest,
+        None => return rest.is_empty() && upper.starts_with('R'),
+    };
+    rest.bytes().all(|c| c.is_ascii_digit())
+}
@@ -0,0 +1,169 @@
+use flate2::{Compress, Compression, FlushCompress, Status};
+use std::collections::VecDeque;
+use std::io::{Error, Result as IoResult, Write};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// The size of the blocks compressed apart, as in pigz.
+const BLOCK_SIZE: usize = 128 * 1024;
+
+/// Compresses a part with several threads, like pigz: it is split in blocks deflated apart, each one ended with a sync flush, so they are joined into a single deflate stream.
+/// The threads only start once the part fills its first block, and only a couple of blocks per thread are kept in memory.
+pub(crate) struct ParallelDeflater<W>
+where
+    W: Write,
+{
+    writer: W,
+    level: u32,
+    threads: usize,
+    block: Vec<u8>,
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    /// The blocks being compressed, in the order they are written.
+    pending: VecDeque<Receiver<IoResult<Vec<u8>>>>,
+}
+
+struct Job {
+    data: Vec<u8>,
+    last: bool,
+    result: SyncSender<IoResult<Vec<u8>>>,
+}
+
+impl<W> ParallelDeflater<W>
+where
+    W: Write,
+{
+    pub(crate) fn new(writer: W, level: u32, threads: usize) -> Self {
+        Self {
+            writer,
+            level,
+            threads,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            jobs: None,
+            workers: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Compresses the last block and waits for all of them, giving back the writer.
+    pub(crate) fn finish(mut self) -> IoResult<W> {
+        let block = std::mem::take(&mut self.block);
+        if self.jobs.is_none() {
+            let compressed = deflate_block(&block, self.level, true)?;
+            self.writer.write_all(&compressed)?;
+        } else {
+            self.compress(block, true)?;
+            while !self.pending.is_empty() {
+                self.write_oldest()?;
+            }
+        }
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        Ok(self.writer)
+    }
+
+    fn compress(&mut self, data: Vec<u8>, last: bool) -> IoResult<()> {
+        if self.jobs.is_none() {
+            self.jobs = Some(self.start_workers());
+        }
+        let (result, receiver) = sync_channel(1);
+        if let Some(jobs) = &self.jobs {
+            jobs.send(Job { data, last, result })
+                .map_err(|_| Error::other("the compression threads stopped"))?;
+        }
+        self.pending.push_back(receiver);
+        while self.pending.len() > self.threads * 2 {
+            self.write_oldest()?;
+        }
+        Ok(())
+    }
+
+    fn start_workers(&mut self) -> Sender<Job> {
+        let (jobs, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let level = self.level;
+        for _ in 0..self.threads {
+            let receiver = receiver.clone();
+            self.workers.push(std::thread::spawn(move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => {
+                        let _ = job.result.send(deflate_block(&job.data, level, job.last));
+                    }
+                    Err(_) => return,
+                }
+            }));
+        }
+        jobs
+    }
+
+    fn write_oldest(&mut self) -> IoResult<()> {
+        if let Some(receiver) = self.pending.pop_front() {
+            let compressed = receiver
+                .recv()
+                .map_err(|_| Error::other("a compression thread stopped"))??;
+            self.writer.write_all(&compressed)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Write for ParallelDeflater<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..written]);
+        if self.block.len() == BLOCK_SIZE {
+            let block = std::mem::replace(&mut self.block, Vec::with_capacity(BLOCK_SIZE));
+            self.compress(block, false)?;
+        }
+        Ok(written)
+    }
+
+    /// Writes the blocks already compressed. The block being filled is kept, as flushing it would end it early.
+    fn flush(&mut self) -> IoResult<()> {
+        while let Some(receiver) = self.pending.front() {
+            match receiver.try_recv() {
+                Ok(compressed) => {
+                    self.pending.pop_front();
+                    self.writer.write_all(&compressed?)?;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(Error::other("a compression thread stopped")),
+            }
+        }
+        self.writer.flush()
+    }
+}
+
+/// Deflates a block on its own. Every block but the last one ends with a sync flush, so the next one starts on a byte boundary, and only the last one is final.
+fn deflate_block(data: &[u8], level: u32, last: bool) -> IoResult<Vec<u8>> {
+    let mut compress = Compress::new(Compression::new(level), false);
+    let mut output = Vec::with_capacity(data.len() / 2 + 64);
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+    loop {
+        if output.len() == output.capacity() {
+            output.reserve(BLOCK_SIZE / 4);
+        }
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut output, flush)
+            .map_err(|err| Error::other(err.to_string()))?;
+        let done = match status {
+            Status::StreamEnd => true,
+            _ => !last && compress.total_in() as usize == data.len() && output.len() < output.capacity(),
+        };
+        if done {
+            return Ok(output);
+        }
+    }
+}
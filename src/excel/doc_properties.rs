@@ -0,0 +1,275 @@
+use crate::excel::row::escape_xml;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The properties of the document shown by Excel in File > Info, written in `docProps/core.xml` and `docProps/app.xml`.
+/// Set them with [WorkBook::set_properties](crate::excel::WorkBook::set_properties).
+#[derive(Clone, Debug, Default)]
+pub struct DocProperties {
+    title: Option<String>,
+    subject: Option<String>,
+    creator: Option<String>,
+    keywords: Option<String>,
+    description: Option<String>,
+    category: Option<String>,
+    company: Option<String>,
+    manager: Option<String>,
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+}
+
+impl DocProperties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    /// The author of the document.
+    pub fn creator(mut self, creator: impl Into<String>) -> Self {
+        self.creator = Some(creator.into());
+        self
+    }
+
+    pub fn keywords(mut self, keywords: impl Into<String>) -> Self {
+        self.keywords = Some(keywords.into());
+        self
+    }
+
+    /// The comments of the document.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    pub fn company(mut self, company: impl Into<String>) -> Self {
+        self.company = Some(company.into());
+        self
+    }
+
+    pub fn manager(mut self, manager: impl Into<String>) -> Self {
+        self.manager = Some(manager.into());
+        self
+    }
+
+    pub fn created(mut self, created: SystemTime) -> Self {
+        self.created = Some(created);
+        self
+    }
+
+    pub fn modified(mut self, modified: SystemTime) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// Writes `docProps/core.xml`.
+    pub(crate) fn write_core(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+"#
+        )?;
+        let texts = [
+            ("dc:title", &self.title),
+            ("dc:subject", &self.subject),
+            ("dc:creator", &self.creator),
+            ("cp:keywords", &self.keywords),
+            ("dc:description", &self.description),
+        ];
+        for (name, value) in texts.iter() {
+            if let Some(value) = value {
+                writeln!(writer, "<{}>{}</{}>", name, escape_xml(value), name)?;
+            }
+        }
+        let dates = [("dcterms:created", self.created), ("dcterms:modified", self.modified)];
+        for (name, value) in dates.iter() {
+            if let Some(value) = value {
+                writeln!(
+                    writer,
+                    "<{} xsi:type=\"dcterms:W3CDTF\">{}</{}>",
+                    name,
+                    w3cdtf(*value),
+                    name
+                )?;
+            }
+        }
+        if let Some(category) = &self.category {
+            writeln!(writer, "<cp:category>{}</cp:category>", escape_xml(category))?;
+        }
+        write!(writer, "</cp:coreProperties>")
+    }
+
+    /// Writes `docProps/app.xml`, listing the sheets of the workbook.
+    pub(crate) fn write_app(&self, writer: &mut impl Write, sheet_names: &[String]) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties" xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes">
+<Application>fastexcel</Application>
+<HeadingPairs><vt:vector size="2" baseType="variant"><vt:variant><vt:lpstr>Worksheets</vt:lpstr></vt:variant><vt:variant><vt:i4>{}</vt:i4></vt:variant></vt:vector></HeadingPairs>
+<TitlesOfParts><vt:vector size="{}" baseType="lpstr">"#,
+            sheet_names.len(),
+            sheet_names.len()
+        )?;
+        for name in sheet_names {
+            write!(writer, "<vt:lpstr>{}</vt:lpstr>", escape_xml(name))?;
+        }
+        writeln!(writer, "</vt:vector></TitlesOfParts>")?;
+        if let Some(manager) = &self.manager {
+            writeln!(writer, "<Manager>{}</Manager>", escape_xml(manager))?;
+        }
+        if let Some(company) = &self.company {
+            writeln!(writer, "<Company>{}</Company>", escape_xml(company))?;
+        }
+        write!(writer, "</Properties>")
+    }
+}
+
+/// Formats a time as the UTC `W3CDTF` dates of the document properties, like `2023-01-31T08:00:00Z`.
+pub(crate) fn w3cdtf(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let days = seconds.div_euclid(86_400);
+    let seconds_of_day = seconds.rem_euclid(86_400);
+    // Converts the days since 1970-01-01 into a civil date, counting eras of 400 years from 0000-03-01.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// The value of a custom document property. Set them with [WorkBook::set_custom_property](crate::excel::WorkBook::set_custom_property).
+#[derive(Clone, Debug, PartialEq)]
+pub enum CustomPropertyValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(SystemTime),
+}
+
+impl From<&str> for CustomPropertyValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for CustomPropertyValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<f64> for CustomPropertyValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<i32> for CustomPropertyValue {
+    fn from(value: i32) -> Self {
+        Self::Number(value.into())
+    }
+}
+
+impl From<bool> for CustomPropertyValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<SystemTime> for CustomPropertyValue {
+    fn from(value: SystemTime) -> Self {
+        Self::Date(value)
+    }
+}
+
+/// The custom properties of the document, written in `docProps/custom.xml`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CustomProperties {
+    list: Vec<(String, CustomPropertyValue)>,
+}
+
+impl CustomProperties {
+    /// Sets a property, replacing the value of the property with the same name. Names are case insensitive.
+    pub(crate) fn set(&mut self, name: &str, value: CustomPropertyValue) -> IoResult<()> {
+        if name.is_empty() || name.chars().count() > 255 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid custom property {}: it must have between 1 and 255 characters", name),
+            ));
+        }
+        if let CustomPropertyValue::Number(number) = value {
+            if !number.is_finite() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid custom property {}: the number {} must be finite", name, number),
+                ));
+            }
+        }
+        match self.list.iter_mut().find(|(existing, _)| existing.eq_ignore_ascii_case(name)) {
+            Some(property) => property.1 = value,
+            None => self.list.push((name.to_string(), value)),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/custom-properties" xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes">
+"#
+        )?;
+        for (i, (name, value)) in self.list.iter().enumerate() {
+            // The ids 0 and 1 are reserved.
+            write!(
+                writer,
+                "<property fmtid=\"{{D5CDD505-2E9C-101B-9397-08002B2CF9AE}}\" pid=\"{}\" name=\"{}\">",
+                i + 2,
+                escape_xml(name)
+            )?;
+            match value {
+                CustomPropertyValue::Text(text) => write!(writer, "<vt:lpwstr>{}</vt:lpwstr>", escape_xml(text))?,
+                CustomPropertyValue::Number(number) => write!(writer, "<vt:r8>{}</vt:r8>", number)?,
+                CustomPropertyValue::Bool(bool) => write!(writer, "<vt:bool>{}</vt:bool>", bool)?,
+                CustomPropertyValue::Date(date) => write!(writer, "<vt:filetime>{}</vt:filetime>", w3cdtf(*date))?,
+            }
+            writeln!(writer, "</property>")?;
+        }
+        write!(writer, "</Properties>")
+    }
+}
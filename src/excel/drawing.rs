@@ -0,0 +1,187 @@
+use crate::excel::relationships::{Relationships, CHART, IMAGE};
+use crate::excel::{Chart, Image, ImageOptions};
+use std::collections::HashMap;
+use std::io::{Result as IoResult, Write};
+
+/// English Metric Units per pixel, at 96 dpi.
+const EMU_PER_PIXEL: f64 = 9525.0;
+
+/// The drawing of a sheet, written as `xl/drawings/drawingN.xml`. It holds the anchors of everything floating over the cells.
+#[derive(Default, Debug)]
+pub(crate) struct Drawing {
+    /// The relationship id of this drawing in the sheet, referenced by the sheet `<drawing>`.
+    pub(crate) relationship_id: Option<usize>,
+    pub(crate) relationships: Relationships,
+    anchors: Vec<Anchor>,
+    media: Vec<Media>,
+    media_ids: HashMap<String, usize>,
+    charts: Vec<ChartPart>,
+}
+
+/// An image embedded in the package as `xl/media/{name}`.
+#[derive(Debug)]
+pub(crate) struct Media {
+    pub(crate) name: String,
+    pub(crate) image: Image,
+}
+
+/// A chart written as `xl/charts/{name}`.
+#[derive(Debug)]
+pub(crate) struct ChartPart {
+    pub(crate) name: String,
+    pub(crate) chart: Chart,
+}
+
+#[derive(Debug)]
+struct Anchor {
+    column_index: usize,
+    row_index: usize,
+    x_offset: u32,
+    y_offset: u32,
+    width: u32,
+    height: u32,
+    content: AnchorContent,
+}
+
+#[derive(Debug)]
+enum AnchorContent {
+    Picture { relationship_id: usize },
+    Chart { relationship_id: usize },
+}
+
+impl Drawing {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    pub(crate) fn media(&self) -> &[Media] {
+        &self.media
+    }
+
+    pub(crate) fn charts(&self) -> &[ChartPart] {
+        &self.charts
+    }
+
+    /// Anchors a chart to a cell.
+    pub(crate) fn add_chart(
+        &mut self,
+        sheet_id: usize,
+        column_index: usize,
+        row_index: usize,
+        chart: Chart,
+    ) {
+        let name = format!("chart{}_{}.xml", sheet_id, self.charts.len() + 1);
+        let relationship_id = self
+            .relationships
+            .add(CHART, format!("../charts/{}", name), false);
+        let (width, height) = chart.dimensions();
+        self.charts.push(ChartPart { name, chart });
+        self.anchors.push(Anchor {
+            column_index,
+            row_index,
+            x_offset: 0,
+            y_offset: 0,
+            width,
+            height,
+            content: AnchorContent::Chart { relationship_id },
+        });
+    }
+
+    /// Anchors an image to a cell. The same image is embedded only once per sheet, no matter how many times it is anchored.
+    pub(crate) fn add_image(
+        &mut self,
+        sheet_id: usize,
+        column_index: usize,
+        row_index: usize,
+        image: &Image,
+        options: &ImageOptions,
+    ) {
+        let key = image.key();
+        let relationship_id = match self.media_ids.get(&key) {
+            Some(id) => *id,
+            None => {
+                let name = format!(
+                    "image{}_{}.{}",
+                    sheet_id,
+                    self.media.len() + 1,
+                    image.format().extension()
+                );
+                let id = self
+                    .relationships
+                    .add(IMAGE, format!("../media/{}", name), false);
+                self.media.push(Media {
+                    name,
+                    image: image.clone(),
+                });
+                self.media_ids.insert(key, id);
+                id
+            }
+        };
+        let (width, height) = image.dimensions();
+        self.anchors.push(Anchor {
+            column_index,
+            row_index,
+            x_offset: options.x_offset,
+            y_offset: options.y_offset,
+            width: (width as f64 * options.x_scale).round() as u32,
+            height: (height as f64 * options.y_scale).round() as u32,
+            content: AnchorContent::Picture { relationship_id },
+        });
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<xdr:wsDr xmlns:xdr="http://schemas.openxmlformats.org/drawingml/2006/spreadsheetDrawing" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+"#
+        )?;
+        for (i, anchor) in self.anchors.iter().enumerate() {
+            let cx = (anchor.width as f64 * EMU_PER_PIXEL) as u64;
+            let cy = (anchor.height as f64 * EMU_PER_PIXEL) as u64;
+            write!(
+                writer,
+                r#"<xdr:oneCellAnchor>
+<xdr:from><xdr:col>{}</xdr:col><xdr:colOff>{}</xdr:colOff><xdr:row>{}</xdr:row><xdr:rowOff>{}</xdr:rowOff></xdr:from>
+<xdr:ext cx="{}" cy="{}"/>
+"#,
+                anchor.column_index,
+                (anchor.x_offset as f64 * EMU_PER_PIXEL) as u64,
+                anchor.row_index - 1,
+                (anchor.y_offset as f64 * EMU_PER_PIXEL) as u64,
+                cx,
+                cy,
+            )?;
+            match anchor.content {
+                AnchorContent::Picture { relationship_id } => write!(
+                    writer,
+                    r#"<xdr:pic>
+<xdr:nvPicPr><xdr:cNvPr id="{}" name="Picture {}"/><xdr:cNvPicPr><a:picLocks noChangeAspect="1"/></xdr:cNvPicPr></xdr:nvPicPr>
+<xdr:blipFill><a:blip r:embed="rId{}"/><a:stretch><a:fillRect/></a:stretch></xdr:blipFill>
+<xdr:spPr><a:xfrm><a:off x="0" y="0"/><a:ext cx="{}" cy="{}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></xdr:spPr>
+</xdr:pic>
+"#,
+                    i + 2,
+                    i + 1,
+                    relationship_id,
+                    cx,
+                    cy
+                )?,
+                AnchorContent::Chart { relationship_id } => write!(
+                    writer,
+                    r#"<xdr:graphicFrame macro="">
+<xdr:nvGraphicFramePr><xdr:cNvPr id="{}" name="Chart {}"/><xdr:cNvGraphicFramePr/></xdr:nvGraphicFramePr>
+<xdr:xfrm><a:off x="0" y="0"/><a:ext cx="0" cy="0"/></xdr:xfrm>
+<a:graphic><a:graphicData uri="http://schemas.openxmlformats.org/drawingml/2006/chart"><c:chart xmlns:c="http://schemas.openxmlformats.org/drawingml/2006/chart" r:id="rId{}"/></a:graphicData></a:graphic>
+</xdr:graphicFrame>
+"#,
+                    i + 2,
+                    i + 1,
+                    relationship_id
+                )?,
+            }
+            write!(writer, "<xdr:clientData/>\n</xdr:oneCellAnchor>\n")?;
+        }
+        write!(writer, "</xdr:wsDr>")
+    }
+}
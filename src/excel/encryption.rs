@@ -0,0 +1,209 @@
+use crate::excel::compound_file::{write_compound_file, MAX_STREAM_LENGTH};
+use crate::excel::protection::{hash_password, IteratorOrder, SPIN_COUNT};
+use aes::Aes256;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cbc::cipher::{block_padding::NoPadding, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha512};
+use std::io::{Error, ErrorKind, Read, Result as IoResult, Write};
+
+const SALT_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+const HASH_SIZE: usize = 64;
+/// The package is encrypted in segments of this size, each one with its own IV.
+pub(crate) const SEGMENT_SIZE: usize = 4096;
+
+/// The block keys of the ECMA-376 Agile Encryption, mixed into the hashes to derive the different keys and IVs.
+pub(crate) const VERIFIER_HASH_INPUT_BLOCK_KEY: [u8; 8] = [0xfe, 0xa7, 0xd2, 0x76, 0x3b, 0x4b, 0x9e, 0x79];
+pub(crate) const VERIFIER_HASH_VALUE_BLOCK_KEY: [u8; 8] = [0xd7, 0xaa, 0x0f, 0x6d, 0x30, 0x61, 0x34, 0x4e];
+pub(crate) const ENCRYPTED_KEY_VALUE_BLOCK_KEY: [u8; 8] = [0x14, 0x6e, 0x0b, 0xe7, 0xab, 0xac, 0xd0, 0xd6];
+pub(crate) const HMAC_KEY_BLOCK_KEY: [u8; 8] = [0x5f, 0xb2, 0xad, 0x01, 0x0c, 0xb9, 0xe1, 0xf6];
+pub(crate) const HMAC_VALUE_BLOCK_KEY: [u8; 8] = [0xa0, 0x67, 0x7f, 0x02, 0xb2, 0x2c, 0x84, 0x33];
+
+/// Encrypts a finished XLSX package with a password using the ECMA-376 Agile Encryption (AES-256 and SHA-512),
+/// and writes it as the OLE compound file Excel expects, with its `EncryptionInfo` and `EncryptedPackage` streams.
+/// The package is read and encrypted one segment at a time, straight into the `EncryptedPackage` stream, so it is never in memory.
+pub(crate) fn write_encrypted_package(
+    writer: &mut impl Write,
+    package: &mut impl Read,
+    package_size: u64,
+    password: &str,
+) -> IoResult<()> {
+    let package_key = random_bytes(KEY_SIZE)?;
+    let key_data_salt = random_bytes(SALT_SIZE)?;
+    let hmac_key = random_bytes(HASH_SIZE)?;
+    let encrypted_hmac_key = encrypt(
+        &package_key,
+        &hash_iv(&key_data_salt, &HMAC_KEY_BLOCK_KEY),
+        &hmac_key,
+    );
+
+    let password_salt = random_bytes(SALT_SIZE)?;
+    let password_hash = hash_password(password, &password_salt, SPIN_COUNT, IteratorOrder::First);
+    let verifier_hash_input = random_bytes(SALT_SIZE)?;
+    let verifier_hash_value = Sha512::digest(&verifier_hash_input);
+    let key_encryptor = KeyEncryptor {
+        password_salt: STANDARD.encode(&password_salt),
+        encrypted_verifier_hash_input: STANDARD.encode(encrypt(
+            &derive_key(&password_hash, &VERIFIER_HASH_INPUT_BLOCK_KEY),
+            &password_salt,
+            &verifier_hash_input,
+        )),
+        encrypted_verifier_hash_value: STANDARD.encode(encrypt(
+            &derive_key(&password_hash, &VERIFIER_HASH_VALUE_BLOCK_KEY),
+            &password_salt,
+            &verifier_hash_value,
+        )),
+        encrypted_key_value: STANDARD.encode(encrypt(
+            &derive_key(&password_hash, &ENCRYPTED_KEY_VALUE_BLOCK_KEY),
+            &password_salt,
+            &package_key,
+        )),
+        key_data_salt: STANDARD.encode(&key_data_salt),
+        encrypted_hmac_key: STANDARD.encode(&encrypted_hmac_key),
+    };
+
+    // The HMAC of the encrypted package is only known once it is written, but its length is fixed, so the length of the encryption info is known before.
+    let info_length = key_encryptor.encryption_info(&[0; HASH_SIZE])?.len();
+    let package_length = encrypted_package_length(package_size) as usize;
+    let mut hmac = Hmac::<Sha512>::new_from_slice(&hmac_key)
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+    let mut hmac_value = None;
+    // The package is listed first, so it is written before the encryption info holding its HMAC.
+    write_compound_file(
+        writer,
+        &[("EncryptedPackage", package_length), ("EncryptionInfo", info_length)],
+        |index, stream| match index {
+            0 => {
+                let size = package_size.to_le_bytes();
+                hmac.update(&size);
+                stream.write_all(&size)?;
+                let mut segment = Vec::with_capacity(SEGMENT_SIZE);
+                let mut read = 0;
+                for i in 0u32.. {
+                    segment.clear();
+                    (&mut *package).take(SEGMENT_SIZE as u64).read_to_end(&mut segment)?;
+                    if segment.is_empty() {
+                        break;
+                    }
+                    read += segment.len() as u64;
+                    let encrypted = encrypt(&package_key, &hash_iv(&key_data_salt, &i.to_le_bytes()), &segment);
+                    hmac.update(&encrypted);
+                    stream.write_all(&encrypted)?;
+                }
+                if read != package_size {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("the package has {} bytes instead of {}", read, package_size),
+                    ));
+                }
+                hmac_value = Some(hmac.clone().finalize().into_bytes());
+                Ok(())
+            }
+            _ => {
+                let hmac_value = hmac_value.ok_or_else(|| Error::other("the package was not encrypted yet"))?;
+                let encrypted_hmac_value = encrypt(
+                    &package_key,
+                    &hash_iv(&key_data_salt, &HMAC_VALUE_BLOCK_KEY),
+                    &hmac_value,
+                );
+                stream.write_all(&key_encryptor.encryption_info(&encrypted_hmac_value)?)
+            }
+        },
+    )
+}
+
+/// The length of the `EncryptedPackage` stream: the size of the package, then the package padded to whole blocks.
+fn encrypted_package_length(package_size: u64) -> u64 {
+    8 + package_size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
+
+/// Checks the encrypted package fits in the compound file, as its streams can't be longer than 2 GiB.
+pub(crate) fn check_package_size(package_size: u64) -> IoResult<()> {
+    if encrypted_package_length(package_size) > MAX_STREAM_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "the package has {} bytes, too big to be encrypted, as it can't be bigger than 2 GiB",
+                package_size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The values of the encryption info known before the package is encrypted, encoded in base64.
+struct KeyEncryptor {
+    key_data_salt: String,
+    encrypted_hmac_key: String,
+    password_salt: String,
+    encrypted_verifier_hash_input: String,
+    encrypted_verifier_hash_value: String,
+    encrypted_key_value: String,
+}
+
+impl KeyEncryptor {
+    /// The `EncryptionInfo` stream, with the encrypted HMAC of the package.
+    fn encryption_info(&self, encrypted_hmac_value: &[u8]) -> IoResult<Vec<u8>> {
+        let cipher = format!(
+            "saltSize=\"{}\" blockSize=\"{}\" keyBits=\"{}\" hashSize=\"{}\" cipherAlgorithm=\"AES\" cipherChaining=\"ChainingModeCBC\" hashAlgorithm=\"SHA512\"",
+            SALT_SIZE,
+            BLOCK_SIZE,
+            KEY_SIZE * 8,
+            HASH_SIZE
+        );
+        let mut encryption_info = Vec::new();
+        // Version 4.4 and the reserved flags of the Agile Encryption.
+        encryption_info.extend_from_slice(&[0x04, 0x00, 0x04, 0x00, 0x40, 0x00, 0x00, 0x00]);
+        write!(
+            encryption_info,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<encryption xmlns="http://schemas.microsoft.com/office/2006/encryption" xmlns:p="http://schemas.microsoft.com/office/2006/keyEncryptor/password" xmlns:c="http://schemas.microsoft.com/office/2006/keyEncryptor/certificate"><keyData {} saltValue="{}"/><dataIntegrity encryptedHmacKey="{}" encryptedHmacValue="{}"/><keyEncryptors><keyEncryptor uri="http://schemas.microsoft.com/office/2006/keyEncryptor/password"><p:encryptedKey spinCount="{}" {} saltValue="{}" encryptedVerifierHashInput="{}" encryptedVerifierHashValue="{}" encryptedKeyValue="{}"/></keyEncryptor></keyEncryptors></encryption>"#,
+            cipher,
+            self.key_data_salt,
+            self.encrypted_hmac_key,
+            STANDARD.encode(encrypted_hmac_value),
+            SPIN_COUNT,
+            cipher,
+            self.password_salt,
+            self.encrypted_verifier_hash_input,
+            self.encrypted_verifier_hash_value,
+            self.encrypted_key_value
+        )?;
+        Ok(encryption_info)
+    }
+}
+
+fn random_bytes(size: usize) -> IoResult<Vec<u8>> {
+    let mut bytes = vec![0; size];
+    getrandom::getrandom(&mut bytes).map_err(|err| Error::other(err.to_string()))?;
+    Ok(bytes)
+}
+
+/// The key for a block key, from the hashed password.
+pub(crate) fn derive_key(password_hash: &[u8], block_key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(password_hash);
+    hasher.update(block_key);
+    hasher.finalize()[..KEY_SIZE].to_vec()
+}
+
+/// The IV for a block key or a segment number, from the salt of the key data.
+pub(crate) fn hash_iv(salt: &[u8], block_key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(salt);
+    hasher.update(block_key);
+    hasher.finalize()[..BLOCK_SIZE].to_vec()
+}
+
+/// Encrypts with AES-256 in CBC mode, padding the data with zeros up to the block size.
+pub(crate) fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut buffer = data.to_vec();
+    let padded = data.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    buffer.resize(padded, 0);
+    cbc::Encryptor::<Aes256>::new(key.into(), iv.into())
+        .encrypt_padded_mut::<NoPadding>(&mut buffer, padded)
+        .expect("the data is padded to the block size");
+    buffer
+}
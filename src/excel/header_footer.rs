@@ -0,0 +1,191 @@
+use crate::excel::row::escape_xml;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// Excel doesn't open header and footer texts longer than this.
+const MAX_LENGTH: usize = 255;
+
+/// The content of a section of a header or a footer, mixing text with fields Excel fills when printing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderFooterSection {
+    codes: String,
+}
+
+impl HeaderFooterSection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds plain text. A `&` in it is printed as is.
+    pub fn text(mut self, text: &str) -> Self {
+        self.codes.push_str(&text.replace('&', "&&"));
+        self
+    }
+
+    /// Adds the current page number (`&P`).
+    pub fn page_number(self) -> Self {
+        self.code("&P")
+    }
+
+    /// Adds the number of pages (`&N`).
+    pub fn page_count(self) -> Self {
+        self.code("&N")
+    }
+
+    /// Adds the printing date (`&D`).
+    pub fn date(self) -> Self {
+        self.code("&D")
+    }
+
+    /// Adds the printing time (`&T`).
+    pub fn time(self) -> Self {
+        self.code("&T")
+    }
+
+    /// Adds the name of the file (`&F`).
+    pub fn file_name(self) -> Self {
+        self.code("&F")
+    }
+
+    /// Adds the name of the sheet (`&A`).
+    pub fn sheet_name(self) -> Self {
+        self.code("&A")
+    }
+
+    fn code(mut self, code: &str) -> Self {
+        self.codes.push_str(code);
+        self
+    }
+}
+
+/// A header or a footer, with its left, center and right sections.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderFooterLine {
+    left: Option<HeaderFooterSection>,
+    center: Option<HeaderFooterSection>,
+    right: Option<HeaderFooterSection>,
+}
+
+impl HeaderFooterLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn left(mut self, section: HeaderFooterSection) -> Self {
+        self.left = Some(section);
+        self
+    }
+
+    pub fn center(mut self, section: HeaderFooterSection) -> Self {
+        self.center = Some(section);
+        self
+    }
+
+    pub fn right(mut self, section: HeaderFooterSection) -> Self {
+        self.right = Some(section);
+        self
+    }
+
+    fn codes(&self) -> String {
+        let sections = [("&L", &self.left), ("&C", &self.center), ("&R", &self.right)];
+        let mut codes = String::new();
+        for (code, section) in sections.iter() {
+            if let Some(section) = section {
+                codes.push_str(code);
+                codes.push_str(&section.codes);
+            }
+        }
+        codes
+    }
+}
+
+/// The printed headers and footers of a sheet. Set it with [Sheet::set_header_footer](crate::excel::Sheet::set_header_footer).
+///
+/// The header and footer are used in every page, unless a different one is set for the first page or for the even pages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderFooter {
+    header: Option<HeaderFooterLine>,
+    footer: Option<HeaderFooterLine>,
+    first_page_header: Option<HeaderFooterLine>,
+    first_page_footer: Option<HeaderFooterLine>,
+    even_page_header: Option<HeaderFooterLine>,
+    even_page_footer: Option<HeaderFooterLine>,
+}
+
+impl HeaderFooter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn header(mut self, line: HeaderFooterLine) -> Self {
+        self.header = Some(line);
+        self
+    }
+
+    pub fn footer(mut self, line: HeaderFooterLine) -> Self {
+        self.footer = Some(line);
+        self
+    }
+
+    pub fn first_page_header(mut self, line: HeaderFooterLine) -> Self {
+        self.first_page_header = Some(line);
+        self
+    }
+
+    pub fn first_page_footer(mut self, line: HeaderFooterLine) -> Self {
+        self.first_page_footer = Some(line);
+        self
+    }
+
+    pub fn even_page_header(mut self, line: HeaderFooterLine) -> Self {
+        self.even_page_header = Some(line);
+        self
+    }
+
+    pub fn even_page_footer(mut self, line: HeaderFooterLine) -> Self {
+        self.even_page_footer = Some(line);
+        self
+    }
+
+    /// Every line as `(element name, codes)`, in the order of the spreadsheetml schema.
+    fn lines(&self) -> Vec<(&'static str, String)> {
+        let lines = [
+            ("oddHeader", &self.header),
+            ("oddFooter", &self.footer),
+            ("evenHeader", &self.even_page_header),
+            ("evenFooter", &self.even_page_footer),
+            ("firstHeader", &self.first_page_header),
+            ("firstFooter", &self.first_page_footer),
+        ];
+        lines
+            .iter()
+            .filter_map(|(name, line)| line.as_ref().map(|line| (*name, line.codes())))
+            .collect()
+    }
+
+    pub(crate) fn validate(&self) -> IoResult<()> {
+        for (name, codes) in self.lines() {
+            if codes.chars().count() > MAX_LENGTH {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("the {} is longer than {} characters", name, MAX_LENGTH),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(writer, "<headerFooter")?;
+        if self.even_page_header.is_some() || self.even_page_footer.is_some() {
+            write!(writer, " differentOddEven=\"1\"")?;
+        }
+        if self.first_page_header.is_some() || self.first_page_footer.is_some() {
+            write!(writer, " differentFirst=\"1\"")?;
+        }
+        write!(writer, ">")?;
+        for (name, codes) in self.lines() {
+            write!(writer, "<{}>{}</{}>", name, escape_xml(&codes), name)?;
+        }
+        writeln!(writer, "</headerFooter>")
+    }
+}
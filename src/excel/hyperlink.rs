@@ -0,0 +1,61 @@
+/// Where a [Hyperlink](Hyperlink) points to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HyperlinkTarget {
+    /// An external resource, like `https://...` or `mailto:...`. Written as an external relationship of the sheet.
+    Url(String),
+    /// A location inside the workbook, like `'Sheet 2'!A1`.
+    Location(String),
+}
+
+/// A cell value that links to an url, an email address or a location inside the workbook.
+///
+/// The cell shows `text` and, unless a style is provided, uses the workbook default hyperlink style.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hyperlink {
+    target: HyperlinkTarget,
+    text: String,
+    tooltip: Option<String>,
+}
+
+impl Hyperlink {
+    /// Creates a link to an external url.
+    pub fn url(url: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::new(HyperlinkTarget::Url(url.into()), text.into())
+    }
+
+    /// Creates a `mailto:` link to the given email address.
+    pub fn mailto(address: &str, text: impl Into<String>) -> Self {
+        Self::new(HyperlinkTarget::Url(format!("mailto:{}", address)), text.into())
+    }
+
+    /// Creates a link to a location inside the workbook, like `'Sheet 2'!A1`.
+    pub fn location(location: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::new(HyperlinkTarget::Location(location.into()), text.into())
+    }
+
+    /// Sets the tooltip shown when hovering the link.
+    pub fn tooltip(mut self, tooltip: impl Into<String>) -> Self {
+        self.tooltip = Some(tooltip.into());
+        self
+    }
+
+    pub fn target(&self) -> &HyperlinkTarget {
+        &self.target
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_tooltip(&self) -> Option<&str> {
+        self.tooltip.as_deref()
+    }
+
+    fn new(target: HyperlinkTarget, text: String) -> Self {
+        Self {
+            target,
+            text,
+            tooltip: None,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// An image that can be inserted into a sheet with [SheetWriter::insert_image](crate::excel::SheetWriter::insert_image).
+///
+/// Only the header is read to find the format and the dimensions. Images created from a path are read again only when the workbook finishes, so inserting thousands of them doesn't keep them in memory.
+#[derive(Clone, Debug)]
+pub struct Image {
+    source: ImageSource,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Clone, Debug)]
+enum ImageSource {
+    Path(PathBuf),
+    Bytes(Arc<Vec<u8>>),
+}
+
+/// The image formats we can embed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+}
+
+/// Where and how big an image is drawn, relative to the cell it is anchored to. Offsets are in pixels, and the scales need to be positive.
+#[derive(Clone, Debug)]
+pub struct ImageOptions {
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub x_scale: f64,
+    pub y_scale: f64,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            x_offset: 0,
+            y_offset: 0,
+            x_scale: 1.0,
+            y_scale: 1.0,
+        }
+    }
+}
+
+impl ImageOptions {
+    pub(crate) fn validate(&self) -> IoResult<()> {
+        for scale in [self.x_scale, self.y_scale] {
+            if !scale.is_finite() || scale <= 0.0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid image scale {}, it must be positive", scale),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Image {
+    /// Creates an image from a file. The file needs to exist until the workbook is finished.
+    pub fn from_path(path: impl AsRef<Path>) -> IoResult<Self> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+        let (format, width, height) = sniff(&mut reader)?;
+        Ok(Self {
+            source: ImageSource::Path(path.to_path_buf()),
+            format,
+            width,
+            height,
+        })
+    }
+
+    /// Creates an image from its encoded bytes. Clones of the image share the same bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> IoResult<Self> {
+        let (format, width, height) = sniff(&mut bytes.as_slice())?;
+        Ok(Self {
+            source: ImageSource::Bytes(Arc::new(bytes)),
+            format,
+            width,
+            height,
+        })
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// Width and height in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Identifies the image content, so the same file or bytes are embedded only once.
+    pub(crate) fn key(&self) -> String {
+        match &self.source {
+            ImageSource::Path(path) => format!("path:{}", path.display()),
+            ImageSource::Bytes(bytes) => format!("bytes:{:p}", Arc::as_ptr(bytes)),
+        }
+    }
+
+    /// Copies the encoded image into the writer.
+    pub(crate) fn copy_to(&self, writer: &mut impl std::io::Write) -> IoResult<()> {
+        match &self.source {
+            ImageSource::Path(path) => {
+                std::io::copy(&mut File::open(path)?, writer)?;
+                Ok(())
+            }
+            ImageSource::Bytes(bytes) => writer.write_all(bytes),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Gif => "gif",
+        }
+    }
+
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+        }
+    }
+}
+
+fn invalid_image(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Finds the format and the dimensions of an image reading as little as possible of it.
+fn sniff(reader: &mut impl Read) -> IoResult<(ImageFormat, u32, u32)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    match header {
+        [0x89, b'P'] => {
+            let mut rest = [0u8; 22];
+            reader.read_exact(&mut rest)?;
+            if &rest[..6] != b"NG\r\n\x1a\n" || &rest[10..14] != b"IHDR" {
+                return Err(invalid_image("invalid png header"));
+            }
+            let width = u32::from_be_bytes([rest[14], rest[15], rest[16], rest[17]]);
+            let height = u32::from_be_bytes([rest[18], rest[19], rest[20], rest[21]]);
+            Ok((ImageFormat::Png, width, height))
+        }
+        [b'G', b'I'] => {
+            let mut rest = [0u8; 8];
+            reader.read_exact(&mut rest)?;
+            if &rest[..4] != b"F87a" && &rest[..4] != b"F89a" {
+                return Err(invalid_image("invalid gif header"));
+            }
+            let width = u16::from_le_bytes([rest[4], rest[5]]) as u32;
+            let height = u16::from_le_bytes([rest[6], rest[7]]) as u32;
+            Ok((ImageFormat::Gif, width, height))
+        }
+        [0xFF, 0xD8] => {
+            let (width, height) = sniff_jpeg(reader)?;
+            Ok((ImageFormat::Jpeg, width, height))
+        }
+        _ => Err(invalid_image("unsupported image format, use png, jpeg or gif")),
+    }
+}
+
+/// Walks the jpeg segments until the start of frame, which has the dimensions.
+fn sniff_jpeg(reader: &mut impl Read) -> IoResult<(u32, u32)> {
+    loop {
+        let mut marker = [0u8; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xFF {
+            return Err(invalid_image("invalid jpeg segment"));
+        }
+        // Markers can be preceded by any number of 0xFF fill bytes.
+        while marker[1] == 0xFF {
+            reader.read_exact(&mut marker[1..])?;
+        }
+        match marker[1] {
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => continue,
+            0xD9 | 0xDA => return Err(invalid_image("jpeg without start of frame")),
+            _ => {}
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length) as u64;
+        if length < 2 {
+            return Err(invalid_image("invalid jpeg segment"));
+        }
+        let is_start_of_frame =
+            matches!(marker[1], 0xC0..=0xCF) && !matches!(marker[1], 0xC4 | 0xC8 | 0xCC);
+        if is_start_of_frame {
+            let mut frame = [0u8; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Ok((width, height));
+        }
+        std::io::copy(&mut reader.by_ref().take(length - 2), &mut std::io::sink())?;
+    }
+}
@@ -8,9 +8,12 @@
 //!
 //! Basically, you just need to pass an output that implements [Write](std::io::Write) and [Sink](std::io::Sink) to the [WorkBook](crate::WorkBook). And while you are writing the file, it wil be written directly to the output already compressed. So, you could stream directly into a file using very little RAM. Or even write to the memory and still not use that much memory because the file will be already compressed.
 //!
+//! When the output can't seek, like the standard output, a pipe or an HTTP response body, create the workbook with [new_streaming](crate::WorkBook::new_streaming) instead.
+//!
 //! ## Example
-//! ```rust
-//! use simple_xlsx_writer::{row, Row, WorkBook};
+//! ```no_run
+//! use fastexcel::excel::{Row, WorkBook};
+//! use fastexcel::row;
 //! use std::fs::File;
 //! use std::io::Write;
 //!
@@ -33,14 +36,41 @@
 //!     Ok(())
 //! }
 //! ```
+mod chart;
+mod column;
+mod comment;
+mod compound_file;
+mod defined_name;
+mod deflate;
+mod doc_properties;
+mod drawing;
+mod encryption;
+mod header_footer;
+mod hyperlink;
+mod image;
+mod package;
+mod page_setup;
+mod protection;
+mod relationships;
 mod row;
 mod sheet;
 mod sheet_writer;
+mod sparkline;
 mod workbook;
 
+pub use chart::{Chart, ChartSeries, ChartType, LegendPosition};
+pub use column::Column;
+pub use doc_properties::{CustomPropertyValue, DocProperties};
+pub use header_footer::{HeaderFooter, HeaderFooterLine, HeaderFooterSection};
+pub use hyperlink::{Hyperlink, HyperlinkTarget};
+pub use image::{Image, ImageFormat, ImageOptions};
+pub use package::{CompressionMethod, PackageWriter};
+pub use page_setup::{Orientation, PageMargins, PageSetup, PaperSize};
+pub use protection::{CellProtection, SheetProtection, WorkbookProtection};
 pub use row::{Cell, CellValue, Row};
-pub use sheet::{Sheet};
+pub use sheet::{ParallelSheet, Sheet};
 pub use sheet_writer::{SheetWriter};
+pub use sparkline::{SparklineGroup, SparklineType};
 pub use workbook::{CellStyle, WorkBook};
 
 #[macro_export]
@@ -58,7 +88,7 @@ macro_rules! row {
 mod tests {
     use super::*;
     use calamine::{open_workbook_from_rs, Reader, Xlsx};
-    use std::io::{Cursor, Result as IoResult};
+    use std::io::{Cursor, Read, Result as IoResult};
 
     // Very simple smoke test.
     #[test]
@@ -67,7 +97,7 @@ mod tests {
         let mut workbook = WorkBook::new(&mut cursor)?;
         let cell_style = workbook.create_cell_style((255, 255, 255), (0, 0, 0));
         let sheet_1 = workbook.get_new_sheet();
-        sheet_1.write_sheet(false, |sheet_writer| {
+        sheet_1.write_sheet(|sheet_writer| {
             sheet_writer.write_row(row!(
                 (1, &cell_style),
                 (10.3, &cell_style),
@@ -78,7 +108,7 @@ mod tests {
             Ok(())
         })?;
         let sheet_2 = workbook.get_new_sheet();
-        sheet_2.write_sheet(false, |sheet_writer| {
+        sheet_2.write_sheet(|sheet_writer| {
             sheet_writer.write_row(row!(1, 2, 3, 4, 4))?;
             sheet_writer.write_row(row!("one", "two", "three"))?;
             sheet_writer.write_row(row!("Another row"))?;
@@ -93,14 +123,14 @@ mod tests {
         let mut cursor = Cursor::new(Vec::new());
         let mut workbook = WorkBook::new(&mut cursor)?;
         let sheet_1 = workbook.get_new_sheet();
-        sheet_1.write_sheet(false, |sheet_writer| {
+        sheet_1.write_sheet(|sheet_writer| {
             sheet_writer.write_row(row!(1, 10.3, 54.3))?;
             sheet_writer.write_row(row!("ola", "text", "tree"))?;
             sheet_writer.write_row(row!(true, false, false, false))?;
             Ok(())
         })?;
         let sheet_2 = workbook.get_new_sheet();
-        sheet_2.write_sheet(false, |sheet_writer| {
+        sheet_2.write_sheet(|sheet_writer| {
             sheet_writer.write_row(row!(1, 2, 3, 4, 4))?;
             sheet_writer.write_row(row!("one", "two", "three"))?;
             sheet_writer.write_row(row!("Another row"))?;
@@ -126,16 +156,1179 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hyperlinks() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let sheet_1 = workbook.get_new_sheet();
+        sheet_1.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!(
+                Hyperlink::url("https://example.com/orders?id=1&v=2", "Order 1")
+                    .tooltip("Open in admin"),
+                Hyperlink::mailto("sales@example.com", "Mail us"),
+                Hyperlink::location("'Sheet 2'!A1", "Details")
+            ))?;
+            sheet_writer.write_row(row!(Hyperlink::url(
+                "https://example.com/orders?id=1&v=2",
+                "Order 1 again"
+            )))?;
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("no links"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains(r#"<hyperlink ref="A1" r:id="rId1" tooltip="Open in admin" display="Order 1"/>"#));
+        assert!(sheet.contains(r#"<hyperlink ref="B1" r:id="rId2" display="Mail us"/>"#));
+        assert!(sheet.contains(r#"<hyperlink ref="C1" location="&apos;Sheet 2&apos;!A1" display="Details"/>"#));
+        assert!(sheet.contains(r#"<hyperlink ref="A2" r:id="rId1" display="Order 1 again"/>"#));
+        let rels = read_part(&cursor, "xl/worksheets/_rels/sheet1.xml.rels");
+        assert!(rels.contains(r#"Target="https://example.com/orders?id=1&amp;v=2" TargetMode="External""#));
+        assert!(rels.contains(r#"Target="mailto:sales@example.com""#));
+        assert!(!part_exists(&cursor, "xl/worksheets/_rels/sheet2.xml.rels"));
+
+        let result = xlsx_to_vec(cursor);
+        assert_eq!(vec!["Order 1", "Mail us", "Details"], result[0][0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("no comments"))
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Amount", 10))?;
+            sheet_writer.add_comment("B1", "Finance", "Imputed from <last> month")?;
+            sheet_writer.add_comment("a1", "Ops", "Header")?;
+            assert!(sheet_writer.add_comment("1A", "Ops", "Invalid").is_err());
+            assert!(sheet_writer.add_comment("b1", "Ops", "Duplicate").is_err());
+            Ok(())
+        })?;
+        workbook.finish()?;
+        assert!(!read_part(&cursor, "xl/comments2.xml").contains("Duplicate"));
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet2.xml");
+        assert!(sheet.contains(r#"<legacyDrawing r:id="rId2"/>"#));
+        let comments = read_part(&cursor, "xl/comments2.xml");
+        assert!(comments.contains("<author>Finance</author>\n<author>Ops</author>"));
+        assert!(comments.contains(r#"<comment ref="B1" authorId="0"><text><r><t xml:space="preserve">Imputed from &lt;last&gt; month</t></r></text></comment>"#));
+        assert!(comments.contains(r#"<comment ref="A1" authorId="1">"#));
+        let vml = read_part(&cursor, "xl/drawings/vmlDrawing2.vml");
+        assert!(vml.contains("<x:Row>0</x:Row><x:Column>1</x:Column>"));
+        let rels = read_part(&cursor, "xl/worksheets/_rels/sheet2.xml.rels");
+        assert!(rels.contains(r#"Target="../comments2.xml""#));
+        assert!(read_part(&cursor, "[Content_Types].xml").contains("/xl/comments2.xml"));
+        assert!(!part_exists(&cursor, "xl/comments1.xml"));
+
+        // A sheet with more than 1023 notes takes several blocks of shape ids, and the next sheet starts after them.
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            for row in 1..=1500 {
+                sheet_writer.add_comment(&format!("A{}", row), "Ops", "Note")?;
+            }
+            sheet_writer.write_row(row!("Notes"))
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.add_comment("A1", "Ops", "Note")?;
+            sheet_writer.write_row(row!("One note"))
+        })?;
+        workbook.finish()?;
+        let first = read_part(&cursor, "xl/drawings/vmlDrawing1.vml");
+        assert!(first.contains("<o:idmap v:ext=\"edit\" data=\"1,2\"/>"));
+        assert!(first.contains("id=\"_x0000_s1025\""));
+        assert!(first.contains("id=\"_x0000_s2047\""));
+        assert!(first.contains("id=\"_x0000_s2049\""));
+        assert!(!first.contains("id=\"_x0000_s2048\""));
+        let second = read_part(&cursor, "xl/drawings/vmlDrawing2.vml");
+        assert!(second.contains("<o:idmap v:ext=\"edit\" data=\"3\"/>"));
+        assert!(second.contains("id=\"_x0000_s3073\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_images() -> IoResult<()> {
+        let png = Image::from_bytes(vec![
+            0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0, 0, 0, 13, b'I', b'H', b'D', b'R',
+            0, 0, 0, 120, 0, 0, 0, 40, 8, 6, 0, 0, 0,
+        ])?;
+        assert_eq!((ImageFormat::Png, (120, 40)), (png.format(), png.dimensions()));
+        // The path is unique to this process and test, so concurrent runs don't share the file.
+        let jpeg_path = std::env::temp_dir().join(format!("fastexcel-{}-test_images.jpeg", std::process::id()));
+        std::fs::write(
+            &jpeg_path,
+            [
+                0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xFF, 0xC0, 0, 11, 8, 0, 30, 0, 20, 1,
+                1, 0x11, 0,
+            ],
+        )?;
+        let jpeg = Image::from_path(&jpeg_path)?;
+        assert_eq!((ImageFormat::Jpeg, (20, 30)), (jpeg.format(), jpeg.dimensions()));
+        assert!(Image::from_bytes(b"not an image".to_vec()).is_err());
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.insert_image("A1", &png, ImageOptions::default())?;
+            sheet_writer.write_row(row!("Product", "Thumbnail"))?;
+            for row in 2..5 {
+                sheet_writer.write_row(row!(format!("Product {}", row)))?;
+                sheet_writer.insert_image(
+                    &format!("B{}", row),
+                    &jpeg,
+                    ImageOptions {
+                        x_offset: 2,
+                        y_offset: 1,
+                        x_scale: 0.5,
+                        y_scale: 0.5,
+                    },
+                )?;
+            }
+            for scale in [0.0, -1.0, f64::NAN] {
+                let options = ImageOptions { x_scale: scale, ..ImageOptions::default() };
+                assert!(sheet_writer.insert_image("C1", &png, options).is_err());
+            }
+            sheet_writer.add_comment("A1", "Ops", "Logo")?;
+            Ok(())
+        })?;
+        workbook.finish()?;
+        std::fs::remove_file(&jpeg_path)?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<drawing r:id=\"rId1\"/>\n<legacyDrawing r:id=\"rId3\"/>"));
+        let drawing = read_part(&cursor, "xl/drawings/drawing1.xml");
+        assert_eq!(4, drawing.matches("<xdr:oneCellAnchor>").count());
+        assert!(drawing.contains("<xdr:from><xdr:col>1</xdr:col><xdr:colOff>19050</xdr:colOff><xdr:row>3</xdr:row><xdr:rowOff>9525</xdr:rowOff></xdr:from>\n<xdr:ext cx=\"95250\" cy=\"142875\"/>"));
+        let rels = read_part(&cursor, "xl/drawings/_rels/drawing1.xml.rels");
+        assert_eq!(2, rels.matches("<Relationship ").count());
+        assert_eq!(29, read_part_bytes(&cursor, "xl/media/image1_1.png").len());
+        assert_eq!(22, read_part_bytes(&cursor, "xl/media/image1_2.jpeg").len());
+        let content_types = read_part(&cursor, "[Content_Types].xml");
+        assert!(content_types.contains("<Default Extension=\"png\" ContentType=\"image/png\"/>"));
+        assert!(content_types.contains("/xl/drawings/drawing1.xml"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_charts() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Day", "Total"))?;
+            for day in 1..8 {
+                sheet_writer.write_row(row!(format!("Day {}", day), day * 10))?;
+            }
+            sheet_writer.insert_chart(
+                "D2",
+                Chart::new(ChartType::Pie).add_series(ChartSeries::new("Sheet 1", "B2:B8")),
+            )
+        })?;
+        let mut summary = workbook.get_new_sheet();
+        assert!(summary
+            .insert_chart("A1", Chart::new(ChartType::Line))
+            .is_err());
+        assert!(summary
+            .insert_chart(
+                "A1",
+                Chart::new(ChartType::Line).add_series(ChartSeries::new("Sheet 1", "B2:Z"))
+            )
+            .is_err());
+        summary.insert_chart(
+            "A3",
+            Chart::new(ChartType::Column)
+                .title("Daily totals")
+                .x_axis_title("Day")
+                .y_axis_title("Total")
+                .legend(Some(LegendPosition::Bottom))
+                .add_series(
+                    ChartSeries::new("Sheet 1", "B2:B8")
+                        .categories("A2:A8")
+                        .name("Total"),
+                ),
+        )?;
+        summary.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Summary"))
+        })?;
+        workbook.finish()?;
+
+        let chart = read_part(&cursor, "xl/charts/chart2_1.xml");
+        assert!(chart.contains("<c:barDir val=\"col\"/>"));
+        assert!(chart.contains("<c:tx><c:v>Total</c:v></c:tx><c:cat><c:strRef><c:f>&apos;Sheet 1&apos;!$A$2:$A$8</c:f></c:strRef></c:cat><c:val><c:numRef><c:f>&apos;Sheet 1&apos;!$B$2:$B$8</c:f></c:numRef></c:val>"));
+        assert!(chart.contains("<a:t>Daily totals</a:t>"));
+        assert!(chart.contains("<c:legendPos val=\"b\"/>"));
+        let pie = read_part(&cursor, "xl/charts/chart1_1.xml");
+        assert!(pie.contains("<c:pieChart>"));
+        assert!(!pie.contains("<c:catAx>"));
+        assert!(read_part(&cursor, "xl/drawings/drawing2.xml").contains("r:id=\"rId1\"/></a:graphicData>"));
+        assert!(read_part(&cursor, "xl/worksheets/sheet2.xml").contains("<drawing r:id=\"rId1\"/>"));
+        let content_types = read_part(&cursor, "[Content_Types].xml");
+        assert!(content_types.contains("/xl/charts/chart1_1.xml"));
+        assert!(content_types.contains("/xl/charts/chart2_1.xml"));
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!(1))?;
+            sheet_writer.insert_chart(
+                "B2",
+                Chart::new(ChartType::Line).add_series(ChartSeries::new("Sheet 9", "A1:A5")),
+            )
+        })?;
+        let error = workbook.finish().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("Sheet 9"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparklines() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("KPI", "Jan", "Feb", "Mar", "Trend"))?;
+            assert!(sheet_writer
+                .add_sparklines(SparklineGroup::new(SparklineType::Line, "B:1", "E"))
+                .is_err());
+            sheet_writer.add_sparklines(
+                SparklineGroup::new(SparklineType::Column, "B:D", "E")
+                    .series_color((0, 128, 255))
+                    .high_point(true)
+                    .negative_points(true),
+            )?;
+            sheet_writer.write_row(row!("Sales", 1, 2, 3))?;
+            sheet_writer.write_row(row!("Costs", 3, -2, 1))?;
+            sheet_writer.write_row_at(6, row!("Taxes", 1, 1, 2))?;
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("KPI", "Jan", "Feb"))?;
+            sheet_writer.add_sparklines(SparklineGroup::new(SparklineType::WinLoss, "B:C", "D"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<x14:sparklineGroup type=\"column\" displayEmptyCellsAs=\"gap\" high=\"1\" negative=\"1\">"));
+        assert!(sheet.contains("<x14:colorSeries rgb=\"FF0080FF\"/>"));
+        // The rows 4 and 5 were skipped, so they don't get a sparkline.
+        assert!(sheet.contains("<x14:sparkline><xm:f>&apos;Sheet 1&apos;!B2:D2</xm:f><xm:sqref>E2</xm:sqref></x14:sparkline>\n<x14:sparkline><xm:f>&apos;Sheet 1&apos;!B3:D3</xm:f><xm:sqref>E3</xm:sqref></x14:sparkline>\n<x14:sparkline><xm:f>&apos;Sheet 1&apos;!B6:D6</xm:f><xm:sqref>E6</xm:sqref></x14:sparkline>\n</x14:sparklines>"));
+        assert!(sheet.trim_end().ends_with("</extLst>\n</worksheet>"));
+        assert!(!read_part(&cursor, "xl/worksheets/sheet2.xml").contains("<extLst>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_defined_names() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.define_name("TaxRate", "=0.21")?;
+        workbook.define_name("DataRange", "'Sheet 1'!$A$1:$B$3")?;
+        workbook.define_local_name("Sheet 2", "DataRange", "'Sheet 2'!$A$1")?;
+        workbook.define_local_name("Sheet 1", "_xlnm._FilterDatabase", "'Sheet 1'!$A$1:$B$3")?;
+        for invalid in [
+            "taxrate", "A1", "xfd1048576", "R", "c", "R1C1", "R2", "1Rate", "Tax Rate", "",
+            "_xlnm.Unknown",
+        ] {
+            assert!(workbook.define_name(invalid, "1").is_err(), "{}", invalid);
+        }
+        assert!(workbook.define_name("_xlnm.Print_Area", "'Sheet 1'!$A$1").is_err());
+        assert!(workbook.define_name("Empty", "").is_err());
+        workbook.define_name("R2D2", "1")?;
+        workbook.define_name("ABCD1", "1")?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Amount", 1))
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Amount", 2))
+        })?;
+        workbook.finish()?;
+
+        let book = read_part(&cursor, "xl/workbook.xml");
+        assert!(book.contains("<definedName name=\"TaxRate\">0.21</definedName>"));
+        assert!(book.contains("<definedName name=\"DataRange\">&apos;Sheet 1&apos;!$A$1:$B$3</definedName>"));
+        assert!(book.contains("<definedName name=\"DataRange\" localSheetId=\"1\">&apos;Sheet 2&apos;!$A$1</definedName>"));
+        assert!(book.contains("<definedName name=\"_xlnm._FilterDatabase\" localSheetId=\"0\" hidden=\"1\">"));
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        workbook.define_local_name("Missing", "Name", "1")?;
+        assert!(workbook.finish().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_setup() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            assert!(sheet_writer.set_page_setup(PageSetup::new().scale(5)).is_err());
+            assert!(sheet_writer
+                .set_page_setup(PageSetup::new().print_area("A1:"))
+                .is_err());
+            sheet_writer.set_page_setup(
+                PageSetup::new()
+                    .orientation(Orientation::Landscape)
+                    .paper_size(PaperSize::A4)
+                    .fit_to_pages(1, 0)
+                    .center_horizontally(true)
+                    .print_gridlines(true)
+                    .print_area("A1:B3")
+                    .repeat_rows(1, 1),
+            )?;
+            sheet_writer.write_row(row!("Customer", "Total"))?;
+            sheet_writer.write_row(row!("ACME", 10))?;
+            assert!(sheet_writer
+                .set_page_setup(PageSetup::new().fit_to_pages(1, 1))
+                .is_err());
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.set_page_setup(PageSetup::new().scale(80))?;
+            sheet_writer.write_row(row!("Other"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<sheetPr><pageSetUpPr fitToPage=\"1\"/></sheetPr>\n<sheetData>"));
+        assert!(sheet.contains("<printOptions horizontalCentered=\"1\" gridLines=\"1\"/>\n<pageMargins left=\"0.7\" right=\"0.7\" top=\"0.75\" bottom=\"0.75\" header=\"0.3\" footer=\"0.3\"/>\n<pageSetup paperSize=\"9\" fitToWidth=\"1\" fitToHeight=\"0\" orientation=\"landscape\"/>"));
+        let sheet = read_part(&cursor, "xl/worksheets/sheet2.xml");
+        assert!(!sheet.contains("<sheetPr>"));
+        assert!(sheet.contains("<pageSetup scale=\"80\"/>"));
+        let book = read_part(&cursor, "xl/workbook.xml");
+        assert!(book.contains("<definedName name=\"_xlnm.Print_Area\" localSheetId=\"0\">&apos;Sheet 1&apos;!$A$1:$B$3</definedName>"));
+        assert!(book.contains("<definedName name=\"_xlnm.Print_Titles\" localSheetId=\"0\">&apos;Sheet 1&apos;!$1:$1</definedName>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_footer() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let mut sheet = workbook.get_new_sheet();
+        assert!(sheet
+            .set_header_footer(HeaderFooter::new().header(
+                HeaderFooterLine::new().left(HeaderFooterSection::new().text(&"x".repeat(300)))
+            ))
+            .is_err());
+        sheet.set_header_footer(
+            HeaderFooter::new()
+                .header(
+                    HeaderFooterLine::new()
+                        .left(HeaderFooterSection::new().text("Sales & Costs"))
+                        .right(HeaderFooterSection::new().date()),
+                )
+                .footer(
+                    HeaderFooterLine::new().center(
+                        HeaderFooterSection::new()
+                            .text("Page ")
+                            .page_number()
+                            .text(" of ")
+                            .page_count(),
+                    ),
+                )
+                .first_page_header(
+                    HeaderFooterLine::new().center(HeaderFooterSection::new().file_name()),
+                ),
+        )?;
+        sheet.write_sheet(|sheet_writer| {
+            sheet_writer.set_page_setup(PageSetup::new())?;
+            sheet_writer.write_row(row!("Data"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<pageSetup/>\n<headerFooter differentFirst=\"1\"><oddHeader>&amp;LSales &amp;&amp; Costs&amp;R&amp;D</oddHeader><oddFooter>&amp;CPage &amp;P of &amp;N</oddFooter><firstHeader>&amp;C&amp;F</firstHeader></headerFooter>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_breaks() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.insert_page_break()?;
+            for customer in ["ACME", "Globex"] {
+                sheet_writer.write_row(row!(customer))?;
+                sheet_writer.write_row(row!("Order", 1))?;
+                sheet_writer.insert_page_break()?;
+                sheet_writer.insert_page_break()?;
+            }
+            sheet_writer.insert_column_break(3)?;
+            sheet_writer.insert_column_break(1)?;
+            assert!(sheet_writer.insert_column_break(0).is_err());
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("No breaks"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<rowBreaks count=\"2\" manualBreakCount=\"2\"><brk id=\"2\" max=\"16383\" man=\"1\"/><brk id=\"4\" max=\"16383\" man=\"1\"/></rowBreaks>\n<colBreaks count=\"2\" manualBreakCount=\"2\"><brk id=\"1\" max=\"1048575\" man=\"1\"/><brk id=\"3\" max=\"1048575\" man=\"1\"/></colBreaks>"));
+        assert!(!read_part(&cursor, "xl/worksheets/sheet2.xml").contains("Breaks"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_properties() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let total_style = workbook.create_cell_style((255, 255, 255), (0, 0, 0));
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_default_row_height(18.0);
+        sheet.set_row_outline_levels(1)?;
+        sheet.set_summary_rows_below(false);
+        assert!(sheet.set_row_outline_levels(8).is_err());
+        sheet.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(
+                row!("Total", 3)
+                    .style(&total_style)
+                    .height(30.0)
+                    .collapsed(true),
+            )?;
+            for i in 1..=3 {
+                sheet_writer.write_row(row!("Detail", i).outline_level(1).hidden(true))?;
+            }
+            assert!(sheet_writer
+                .write_row(row!("Too deep").outline_level(8))
+                .is_err());
+            for height in [-1.0, 410.0, f64::NAN, f64::INFINITY] {
+                assert!(sheet_writer.write_row(row!("Too tall").height(height)).is_err());
+            }
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Plain"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<sheetPr><outlinePr summaryBelow=\"0\"/></sheetPr>\n<sheetFormatPr defaultRowHeight=\"18\" customHeight=\"1\" outlineLevelRow=\"1\"/>\n<sheetData>"));
+        assert!(sheet.contains("<row r=\"1\" s=\"2\" customFormat=\"1\" ht=\"30\" customHeight=\"1\" collapsed=\"1\">"));
+        assert!(sheet.contains("<c r=\"A1\" t=\"str\" s=\"2\">"));
+        assert!(sheet.contains("<row r=\"4\" hidden=\"1\" outlineLevel=\"1\">"));
+        let plain = read_part(&cursor, "xl/worksheets/sheet2.xml");
+        assert!(!plain.contains("sheetFormatPr"));
+        assert!(plain.contains("<row r=\"1\">"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_groups() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_columns("B:D", Column::new().outline_level(1).hidden(true))?;
+        sheet.set_columns("E", Column::new().collapsed(true))?;
+        sheet.set_columns("F:H", Column::new().outline_level(1))?;
+        sheet.set_columns("G", Column::new().outline_level(2))?;
+        sheet.set_summary_columns_right(false);
+        assert!(sheet.set_columns("I", Column::new().outline_level(8)).is_err());
+        assert!(sheet.set_columns("1:2", Column::new()).is_err());
+        sheet.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Item", "Jan", "Feb", "Mar", "Q1"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<sheetPr><outlinePr summaryRight=\"0\"/></sheetPr>\n<sheetFormatPr defaultRowHeight=\"15\" outlineLevelCol=\"2\"/>\n<cols><col min=\"2\" max=\"4\" width=\"9.140625\" hidden=\"1\" outlineLevel=\"1\"/><col min=\"5\" max=\"5\" width=\"9.140625\" collapsed=\"1\"/><col min=\"6\" max=\"6\" width=\"9.140625\" outlineLevel=\"1\"/><col min=\"7\" max=\"7\" width=\"9.140625\" outlineLevel=\"2\"/><col min=\"8\" max=\"8\" width=\"9.140625\" outlineLevel=\"1\"/></cols>\n<sheetData>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_rows() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let border_style = workbook.create_cell_style((0, 0, 0), (200, 200, 200));
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Title"))?;
+            let mut row = row!("Block");
+            row.add_cell_at("D", 4.into())?;
+            row.add_cell(5.into());
+            row.add_cell_at("AA", Cell::blank(&border_style))?;
+            assert!(row.add_cell_at("C", 3.into()).is_err());
+            assert!(row.add_cell_at("3", 3.into()).is_err());
+            assert!(row.add_cell_at("AD4", 3.into()).is_err());
+            assert!(row.add_cell_at("", 3.into()).is_err());
+            sheet_writer.write_row_at(10, row)?;
+            sheet_writer.write_row(row!("After"))?;
+            assert!(sheet_writer.write_row_at(11, row!("Again")).is_err());
+            assert!(sheet_writer.write_row_at(2_000_000, row!("Too far")).is_err());
+            Ok(())
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<row r=\"10\">\n<c r=\"A10\" t=\"str\"><v>Block</v></c>\n<c r=\"D10\"><v>4</v></c>\n<c r=\"E10\"><v>5</v></c>\n<c r=\"AA10\" s=\"2\"/>\n\n</row>"));
+        assert!(sheet.contains("<row r=\"11\">"));
+        assert!(!sheet.contains("<row r=\"2\">"));
+
+        let mut excel: Xlsx<_> = open_workbook_from_rs(Cursor::new(cursor.into_inner())).unwrap();
+        let range = excel.worksheet_range("Sheet 1").unwrap().unwrap();
+        assert_eq!(range.get_value((9, 3)), Some(&calamine::DataType::Float(4.0)));
+        assert_eq!(range.get_value((10, 0)), Some(&calamine::DataType::String("After".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dimension() -> IoResult<()> {
+        let mut sheet_cursor = Cursor::new(Vec::new());
+        let mut sheet_writer = SheetWriter::start_seekable(&mut sheet_cursor)?;
+        assert_eq!(sheet_writer.dimension(), "A1");
+        let mut row = Row::new();
+        row.add_cell_at("B", "Name".into())?;
+        row.add_cell_at("D", 1.into())?;
+        sheet_writer.write_row_at(2, row)?;
+        sheet_writer.write_row_at(12, row!("Total"))?;
+        assert_eq!(sheet_writer.dimension(), "A2:D12");
+        sheet_writer.finish()?;
+        let sheet = String::from_utf8(sheet_cursor.into_inner()).unwrap();
+        assert!(sheet.contains("<dimension ref=\"A2:D12\"       />\n<sheetData>"));
+        assert!(sheet.ends_with("</worksheet>\n"));
+
+        // Deflated, as by default, the sheet is compressed while it is written, so only a declared dimension is written.
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let mut sheet = workbook.get_new_sheet();
+        assert!(sheet.set_dimension("A1:").is_err());
+        sheet.set_dimension("a1:c2")?;
+        sheet.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!(1, 2, 3))?;
+            sheet_writer.write_row(row!(4, 5, 6))
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Undeclared"))
+        })?;
+        workbook.finish()?;
+        assert!(read_part(&cursor, "xl/worksheets/sheet1.xml").contains("<dimension ref=\"A1:C2\"/>"));
+        assert!(!read_part(&cursor, "xl/worksheets/sheet2.xml").contains("<dimension"));
+        assert_eq!(xlsx_to_vec(cursor)[0][1], vec!["4", "5", "6"]);
+
+        // Stored into a seekable output, the dimension is patched in place.
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.set_compression_method(CompressionMethod::Stored);
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_dimension("A1:Z100")?;
+        sheet.write_sheet(|sheet_writer| sheet_writer.write_row_at(3, row!(1, 2)))?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| sheet_writer.write_row(row!("Undeclared")))?;
+        workbook.finish()?;
+        assert!(read_part(&cursor, "xl/worksheets/sheet1.xml").contains("<dimension ref=\"A3:B3\"        />"));
+        assert!(read_part(&cursor, "xl/worksheets/sheet2.xml").contains("<dimension ref=\"A1\"           />"));
+        assert_eq!(xlsx_to_vec(cursor)[1], vec![vec!["Undeclared"]]);
+
+        // Streamed, the declared dimension is written as it is.
+        let mut workbook = WorkBook::new_streaming(Vec::new());
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_dimension("A1:C2")?;
+        sheet.write_sheet(|sheet_writer| sheet_writer.write_row(row!(1, 2, 3)))?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| sheet_writer.write_row(row!("Undeclared")))?;
+        let cursor = Cursor::new(workbook.finish_into_inner()?);
+        assert!(read_part(&cursor, "xl/worksheets/sheet1.xml").contains("<dimension ref=\"A1:C2\"/>"));
+        assert!(!read_part(&cursor, "xl/worksheets/sheet2.xml").contains("<dimension"));
+
+        // The row doesn't fit in the declared dimension.
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_dimension("A1:C2")?;
+        let error = sheet
+            .write_sheet(|sheet_writer| {
+                sheet_writer.write_row(row!(1, 2, 3))?;
+                sheet_writer.write_row(row!(4, 5, 6))?;
+                sheet_writer.write_row(row!(7, 8, 9))
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("A3:C3"));
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_dimension("B1:C10")?;
+        let error = sheet.write_sheet(|sheet_writer| sheet_writer.write_row(row!(1, 2))).unwrap_err();
+        assert!(error.to_string().contains("A1:B1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sheet_protection() -> IoResult<()> {
+        let mut attributes = Vec::new();
+        let salt: Vec<u8> = (0..16).collect();
+        protection::PasswordHash::with_salt("secret", &salt, protection::SPIN_COUNT).write_attributes(&mut attributes, "")?;
+        assert_eq!(
+            String::from_utf8(attributes).unwrap(),
+            " algorithmName=\"SHA-512\" hashValue=\"M5SOVnbQG4SHyBnRVAYzAx8mPtxyyzMuWxcMv7tkyFO3MBXX9OJjklwPglNHdoHVkKPm4MPfUblqHmAsXfF5HA==\" saltValue=\"AAECAwQFBgcICQoLDA0ODw==\" spinCount=\"100000\""
+        );
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let input_style = workbook.create_cell_style_with_protection(
+            (0, 0, 0),
+            (255, 255, 200),
+            CellProtection::new().locked(false),
+        );
+        let formula_style = workbook.create_cell_style_with_protection(
+            (0, 0, 0),
+            (255, 255, 255),
+            CellProtection::new().hidden(true),
+        );
+        assert!(!input_style.is_locked() && !input_style.is_hidden());
+        assert!(formula_style.is_locked() && formula_style.is_hidden());
+        let mut sheet = workbook.get_new_sheet();
+        sheet.set_protection(
+            SheetProtection::new()
+                .password("secret")?
+                .format_columns(true)
+                .sort(true)
+                .select_locked_cells(false),
+        );
+        sheet.write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!((10, &input_style), (20, &formula_style)))
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Open"))
+        })?;
+        workbook.finish()?;
+
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        let protection = sheet
+            .split("</sheetData>\n<sheetProtection algorithmName=\"SHA-512\" hashValue=\"")
+            .nth(1)
+            .unwrap();
+        assert!(protection.contains("spinCount=\"100000\" sheet=\"1\" objects=\"1\" scenarios=\"1\" formatColumns=\"0\" selectLockedCells=\"1\" sort=\"0\"/>"));
+        assert!(!protection.contains("secret"));
+        assert!(!read_part(&cursor, "xl/worksheets/sheet2.xml").contains("sheetProtection"));
+        let styles = read_part(&cursor, "xl/styles.xml");
+        assert!(styles.contains("applyProtection=\"1\"><protection locked=\"0\" hidden=\"0\"/></xf>"));
+        assert!(styles.contains("applyProtection=\"1\"><protection locked=\"1\" hidden=\"1\"/></xf>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_workbook_protection() -> IoResult<()> {
+        let mut attributes = Vec::new();
+        let salt: Vec<u8> = (0..16).collect();
+        protection::PasswordHash::with_salt("secret", &salt, protection::SPIN_COUNT)
+            .write_attributes(&mut attributes, "workbook")?;
+        assert_eq!(
+            String::from_utf8(attributes).unwrap(),
+            " workbookAlgorithmName=\"SHA-512\" workbookHashValue=\"M5SOVnbQG4SHyBnRVAYzAx8mPtxyyzMuWxcMv7tkyFO3MBXX9OJjklwPglNHdoHVkKPm4MPfUblqHmAsXfF5HA==\" workbookSaltValue=\"AAECAwQFBgcICQoLDA0ODw==\" workbookSpinCount=\"100000\""
+        );
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.set_protection(WorkbookProtection::new().lock_windows(true))?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Locked"))
+        })?;
+        workbook.finish()?;
+        let workbook_xml = read_part(&cursor, "xl/workbook.xml");
+        assert!(workbook_xml.contains("<workbookPr date1904=\"false\"/>\n<workbookProtection lockStructure=\"1\" lockWindows=\"1\"/>\n            <sheets>"));
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.set_protection(WorkbookProtection::new().password("secret")?)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Locked"))
+        })?;
+        workbook.finish()?;
+        let workbook_xml = read_part(&cursor, "xl/workbook.xml");
+        assert!(workbook_xml.contains("<workbookProtection workbookAlgorithmName=\"SHA-512\" workbookHashValue=\""));
+        assert!(workbook_xml.contains("workbookSpinCount=\"100000\" lockStructure=\"1\"/>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let workbook = WorkBook::new(&mut cursor)?;
+        assert!(workbook.finish_with_password("").is_err());
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Payroll", 1234.5))
+        })?;
+        workbook.finish_with_password("secret")?;
+        let file = cursor.into_inner();
+        assert_eq!(file[..8], [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+        assert_eq!(file.len() % 512, 0);
+        assert!(zip::ZipArchive::new(Cursor::new(&file)).is_err());
+        let text = String::from_utf8_lossy(&file);
+        assert!(text.contains("<keyData saltSize=\"16\" blockSize=\"16\" keyBits=\"256\" hashSize=\"64\" cipherAlgorithm=\"AES\" cipherChaining=\"ChainingModeCBC\" hashAlgorithm=\"SHA512\""));
+        assert!(!text.contains("Payroll"));
+
+        // A version 3 compound file can't hold a stream longer than 2 GiB.
+        assert!(encryption::check_package_size(0x8000_0000 - 16).is_ok());
+        assert_eq!(encryption::check_package_size(0x8000_0000 - 8).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        let mut output = Vec::new();
+        let result = compound_file::write_compound_file(&mut output, &[("Big", 0x8000_0001)], |_, _| Ok(()));
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decryption() -> IoResult<()> {
+        use aes::Aes256;
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use cbc::cipher::{block_padding::NoPadding, BlockDecryptMut, KeyIvInit};
+        use hmac::{Hmac, Mac};
+        use sha2::{Digest, Sha512};
+        use std::convert::TryInto;
+        let decrypt = |key: &[u8], iv: &[u8], data: &[u8]| -> Vec<u8> {
+            let mut buffer = data.to_vec();
+            cbc::Decryptor::<Aes256>::new(key.into(), iv.into())
+                .decrypt_padded_mut::<NoPadding>(&mut buffer)
+                .unwrap();
+            buffer
+        };
+        let attribute = |xml: &str, element: &str, name: &str| -> Vec<u8> {
+            let element = &xml[xml.find(&format!("<{} ", element)).unwrap()..];
+            let value = &element[element.find(&format!(" {}=\"", name)).unwrap() + name.len() + 3..];
+            STANDARD.decode(&value[..value.find('"').unwrap()]).unwrap()
+        };
+        let hex = |hex: &str| -> Vec<u8> {
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect()
+        };
+
+        // Known answers computed apart from this crate, with Python's hashlib following MS-OFFCRYPTO 2.3.4.11 and with `openssl enc -aes-256-cbc -nopad`.
+        let salt: Vec<u8> = (0..16).collect();
+        let password_hash = protection::hash_password("Password1234_", &salt, protection::SPIN_COUNT, protection::IteratorOrder::First);
+        assert_eq!(password_hash, hex("1154708599656ec9fff5342f72c700ee6d5a0d7ea340f6701f29a7e6159615113d72f0c919cc783d1aee8a570737908f74baf2d342d38d0397984163cfe29fed"));
+        let package_key = encryption::derive_key(&password_hash, &encryption::ENCRYPTED_KEY_VALUE_BLOCK_KEY);
+        assert_eq!(package_key, hex("7a8b2091cd76dd40577bbc7b165de0985a9de0e0aded58ce94fc4b35294c0d0e"));
+        let iv = encryption::hash_iv(&salt, &encryption::HMAC_KEY_BLOCK_KEY);
+        assert_eq!(iv, hex("d13d7a5b62ff84a9482cdb582216930c"));
+        assert_eq!(encryption::encrypt(&package_key, &iv, b"fastexcel secret"), hex("07819f1cd05b95dae42ea761e5af829e"));
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            for i in 0..2000 {
+                sheet_writer.write_row(row!(format!("Payroll {}", i), i as f64))?;
+            }
+            Ok(())
+        })?;
+        workbook.finish_with_password("secret")?;
+        let mut file = cfb::CompoundFile::open(Cursor::new(cursor.into_inner()))?;
+        let mut info = Vec::new();
+        file.open_stream("EncryptionInfo")?.read_to_end(&mut info)?;
+        let mut package = Vec::new();
+        file.open_stream("EncryptedPackage")?.read_to_end(&mut package)?;
+        let info = String::from_utf8(info[8..].to_vec()).unwrap();
+
+        // The key is derived from the password, and checked with the verifier.
+        let password_salt = attribute(&info, "p:encryptedKey", "saltValue");
+        let password_hash = protection::hash_password("secret", &password_salt, protection::SPIN_COUNT, protection::IteratorOrder::First);
+        let key = |block_key: &[u8], name: &str| {
+            decrypt(
+                &encryption::derive_key(&password_hash, block_key),
+                &password_salt,
+                &attribute(&info, "p:encryptedKey", name),
+            )
+        };
+        let verifier_hash_input = key(&encryption::VERIFIER_HASH_INPUT_BLOCK_KEY, "encryptedVerifierHashInput");
+        let verifier_hash_value = key(&encryption::VERIFIER_HASH_VALUE_BLOCK_KEY, "encryptedVerifierHashValue");
+        assert_eq!(Sha512::digest(&verifier_hash_input[..16]).to_vec(), verifier_hash_value);
+        let package_key = key(&encryption::ENCRYPTED_KEY_VALUE_BLOCK_KEY, "encryptedKeyValue");
+
+        // The HMAC covers the whole encrypted package.
+        let key_data_salt = attribute(&info, "keyData", "saltValue");
+        let hmac_key = decrypt(
+            &package_key,
+            &encryption::hash_iv(&key_data_salt, &encryption::HMAC_KEY_BLOCK_KEY),
+            &attribute(&info, "dataIntegrity", "encryptedHmacKey"),
+        );
+        let hmac_value = decrypt(
+            &package_key,
+            &encryption::hash_iv(&key_data_salt, &encryption::HMAC_VALUE_BLOCK_KEY),
+            &attribute(&info, "dataIntegrity", "encryptedHmacValue"),
+        );
+        let mut hmac = Hmac::<Sha512>::new_from_slice(&hmac_key).unwrap();
+        hmac.update(&package);
+        assert_eq!(hmac.finalize().into_bytes().to_vec(), hmac_value);
+
+        // The segments decrypt back to the ZIP package.
+        let size = u64::from_le_bytes(package[..8].try_into().unwrap()) as usize;
+        assert!(size > encryption::SEGMENT_SIZE);
+        let mut decrypted = Vec::new();
+        for (i, segment) in package[8..].chunks(encryption::SEGMENT_SIZE).enumerate() {
+            let iv = encryption::hash_iv(&key_data_salt, &(i as u32).to_le_bytes());
+            decrypted.extend_from_slice(&decrypt(&package_key, &iv, segment));
+        }
+        assert_eq!(&decrypted[..4], b"PK\x03\x04");
+        decrypted.truncate(size);
+        assert_eq!(xlsx_to_vec(Cursor::new(decrypted))[0][1999], vec!["Payroll 1999", "1999"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_doc_properties() -> IoResult<()> {
+        use std::time::{Duration, UNIX_EPOCH};
+        assert_eq!(doc_properties::w3cdtf(UNIX_EPOCH + Duration::from_secs(951_786_061)), "2000-02-29T01:01:01Z");
+        assert_eq!(doc_properties::w3cdtf(UNIX_EPOCH - Duration::from_secs(31_536_000)), "1969-01-01T00:00:00Z");
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.set_properties(
+            DocProperties::new()
+                .title("Payroll <2023>")
+                .creator("Finance & HR")
+                .keywords("payroll, monthly")
+                .category("Reports")
+                .company("ACME")
+                .manager("Jane")
+                .created(UNIX_EPOCH + Duration::from_secs(1_675_152_000)),
+        )?;
+        for _ in 0..2 {
+            workbook.get_new_sheet().write_sheet(|sheet_writer| {
+                sheet_writer.write_row(row!("Data"))
+            })?;
+        }
+        workbook.finish()?;
+
+        let core = read_part(&cursor, "docProps/core.xml");
+        assert!(core.contains("<dc:title>Payroll &lt;2023&gt;</dc:title>\n<dc:creator>Finance &amp; HR</dc:creator>\n<cp:keywords>payroll, monthly</cp:keywords>\n<dcterms:created xsi:type=\"dcterms:W3CDTF\">2023-01-31T08:00:00Z</dcterms:created>\n<cp:category>Reports</cp:category>"));
+        assert!(!core.contains("dcterms:modified"));
+        let app = read_part(&cursor, "docProps/app.xml");
+        assert!(!app.contains("SheetJS"));
+        assert!(app.contains("<vt:i4>2</vt:i4>"));
+        assert!(app.contains("<vt:vector size=\"2\" baseType=\"lpstr\"><vt:lpstr>Sheet 1</vt:lpstr><vt:lpstr>Sheet 2</vt:lpstr></vt:vector>"));
+        assert!(app.contains("<Manager>Jane</Manager>\n<Company>ACME</Company>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_properties() -> IoResult<()> {
+        use std::time::{Duration, UNIX_EPOCH};
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.set_custom_property("Classification", "Internal")?;
+        workbook.set_custom_property("SourceSystem", "billing & co")?;
+        workbook.set_custom_property("classification", "Confidential")?;
+        workbook.set_custom_property("Revision", 3)?;
+        workbook.set_custom_property("Approved", true)?;
+        workbook.set_custom_property("Exported", UNIX_EPOCH + Duration::from_secs(1_675_152_000))?;
+        assert!(workbook.set_custom_property("", 1).is_err());
+        assert!(workbook.set_custom_property("Ratio", f64::NAN).is_err());
+        assert!(workbook.set_custom_property("Ratio", f64::NEG_INFINITY).is_err());
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Data"))
+        })?;
+        workbook.finish()?;
+
+        let custom = read_part(&cursor, "docProps/custom.xml");
+        assert!(custom.contains("pid=\"2\" name=\"Classification\"><vt:lpwstr>Confidential</vt:lpwstr></property>"));
+        assert!(custom.contains("pid=\"3\" name=\"SourceSystem\"><vt:lpwstr>billing &amp; co</vt:lpwstr></property>"));
+        assert!(custom.contains("name=\"Revision\"><vt:r8>3</vt:r8>"));
+        assert!(custom.contains("name=\"Approved\"><vt:bool>true</vt:bool>"));
+        assert!(custom.contains("name=\"Exported\"><vt:filetime>2023-01-31T08:00:00Z</vt:filetime>"));
+        assert!(read_part(&cursor, "_rels/.rels").contains("Target=\"docProps/custom.xml\""));
+        assert!(read_part(&cursor, "[Content_Types].xml").contains("PartName=\"/docProps/custom.xml\""));
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!("Data"))
+        })?;
+        workbook.finish()?;
+        assert!(!part_exists(&cursor, "docProps/custom.xml"));
+        assert!(!read_part(&cursor, "_rels/.rels").contains("custom"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming() -> IoResult<()> {
+        /// A writer that can't seek, like a pipe.
+        struct Pipe(Vec<u8>);
+
+        impl std::io::Write for Pipe {
+            fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+                self.0.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> IoResult<()> {
+                Ok(())
+            }
+        }
+
+        let mut workbook = WorkBook::new_streaming(Pipe(Vec::new()));
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!["Name", "Amount"])?;
+            for i in 0..1000 {
+                sheet_writer.write_row(row![format!("Item {}", i), i as f64])?;
+            }
+            Ok(())
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!["Second"])
+        })?;
+        let Pipe(bytes) = workbook.finish_into_inner()?;
+
+        // Every local header has the data descriptor flag.
+        assert_eq!(&bytes[0..4], b"PK\x03\x04");
+        assert_eq!(bytes[6] & 0x08, 0x08);
+        let cursor = Cursor::new(bytes);
+        assert!(read_part(&cursor, "xl/workbook.xml").contains("<sheet name=\"Sheet 2\""));
+        let result = xlsx_to_vec(cursor);
+        assert_eq!(result[0].len(), 1001);
+        assert_eq!(result[0][1000], vec!["Item 999", "999"]);
+        assert_eq!(result[1][0], vec!["Second"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression() -> IoResult<()> {
+        let write = |method: CompressionMethod, level: u32| -> IoResult<Cursor<Vec<u8>>> {
+            let mut cursor = Cursor::new(Vec::new());
+            let mut workbook = WorkBook::new(&mut cursor)?;
+            workbook.set_compression_method(method);
+            workbook.set_compression_level(level)?;
+            workbook.get_new_sheet().write_sheet(|sheet_writer| {
+                for i in 0..2000 {
+                    sheet_writer.write_row(row![format!("Row {}", i), i as f64])?;
+                }
+                Ok(())
+            })?;
+            workbook.finish()?;
+            Ok(cursor)
+        };
+        let stored = write(CompressionMethod::Stored, 0)?;
+        let fast = write(CompressionMethod::Deflated, 1)?;
+        let best = write(CompressionMethod::Deflated, 9)?;
+        let mut archive = zip::ZipArchive::new(Cursor::new(stored.get_ref().clone())).unwrap();
+        for i in 0..archive.len() {
+            assert_eq!(archive.by_index(i).unwrap().compression(), zip::CompressionMethod::Stored);
+        }
+        assert!(stored.get_ref().len() > fast.get_ref().len());
+        assert!(fast.get_ref().len() > best.get_ref().len());
+        for cursor in [stored, fast, best].iter() {
+            assert_eq!(xlsx_to_vec(cursor.clone())[0][1999], vec!["Row 1999", "1999"]);
+        }
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        assert!(workbook.set_compression_level(10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip64_only_when_needed() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!["Small", "sheet"])
+        })?;
+        workbook.finish()?;
+
+        // Parts smaller than 4 GiB have plain 32 bits sizes, without any ZIP64 extra field.
+        let bytes = cursor.get_ref();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes.clone())).unwrap();
+        for i in 0..archive.len() {
+            let part = archive.by_index(i).unwrap();
+            let header = part.header_start() as usize;
+            assert_eq!(&bytes[header..header + 4], b"PK\x03\x04");
+            assert_eq!(u16::from_le_bytes([bytes[header + 4], bytes[header + 5]]), 20);
+            assert_eq!(u16::from_le_bytes([bytes[header + 28], bytes[header + 29]]), 0);
+            assert_eq!(
+                u32::from_le_bytes([bytes[header + 22], bytes[header + 23], bytes[header + 24], bytes[header + 25]]) as u64,
+                part.size()
+            );
+        }
+        assert!(!bytes.windows(4).any(|window| window == b"PK\x06\x06"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_sheets() -> IoResult<()> {
+        let write = |cursor: &mut Cursor<Vec<u8>>| -> IoResult<()> {
+            let mut workbook = WorkBook::new(cursor)?;
+            workbook.get_new_sheet().write_sheet(|sheet_writer| {
+                sheet_writer.write_row(row!["First"])
+            })?;
+            let functions = (0..3)
+                .map(|n| {
+                    move |mut sheet: ParallelSheet| {
+                        sheet.set_dimension("A1:C1000")?;
+                        sheet.write_sheet(|sheet_writer| {
+                            for i in 0..500 {
+                                sheet_writer.write_row(row![format!("Sheet {} row {}", n, i), i as f64])?;
+                            }
+                            Ok(())
+                        })
+                    }
+                })
+                .collect();
+            workbook.write_sheets_in_parallel(functions)?;
+            workbook.get_new_sheet().write_sheet(|sheet_writer| {
+                sheet_writer.write_row(row!["Last"])
+            })?;
+            workbook.finish()
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        write(&mut cursor)?;
+
+        // The sheets are deflated while they are written, so the declared dimension is kept.
+        assert!(read_part(&cursor, "xl/worksheets/sheet3.xml").contains("<dimension ref=\"A1:C1000\"/>"));
+        assert!(read_part(&cursor, "xl/workbook.xml").contains("<sheet name=\"Sheet 5\""));
+        let mut xlsx_reader: Xlsx<_> = open_workbook_from_rs(cursor).unwrap();
+        assert_eq!(xlsx_reader.sheet_names().len(), 5);
+        let last = xlsx_reader.worksheet_range("Sheet 5").unwrap().unwrap();
+        assert_eq!(last.get_value((0, 0)).unwrap().to_string(), "Last");
+        for n in 0..3 {
+            let range = xlsx_reader.worksheet_range(&format!("Sheet {}", n + 2)).unwrap().unwrap();
+            assert_eq!(range.height(), 500);
+            assert_eq!(range.get_value((499, 0)).unwrap().to_string(), format!("Sheet {} row 499", n));
+        }
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        let result = workbook.write_sheets_in_parallel(vec![|_sheet: ParallelSheet| Ok(())]);
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_threads() -> IoResult<()> {
+        let write = |threads: usize| -> IoResult<Cursor<Vec<u8>>> {
+            let mut cursor = Cursor::new(Vec::new());
+            let mut workbook = WorkBook::new(&mut cursor)?;
+            workbook.set_compression_threads(threads)?;
+            workbook.get_new_sheet().write_sheet(|sheet_writer| {
+                for i in 0..20000 {
+                    sheet_writer.write_row(row![format!("Row {}", i), i as f64, "Some text to compress"])?;
+                }
+                Ok(())
+            })?;
+            workbook.finish()?;
+            Ok(cursor)
+        };
+        let single = write(1)?;
+        let parallel = write(4)?;
+        // The sheet is bigger than a block, so it is joined from several deflate blocks.
+        assert!(read_part_bytes(&parallel, "xl/worksheets/sheet1.xml").len() > 1024 * 1024);
+        assert_eq!(
+            read_part_bytes(&parallel, "xl/worksheets/sheet1.xml"),
+            read_part_bytes(&single, "xl/worksheets/sheet1.xml")
+        );
+        assert_eq!(xlsx_to_vec(parallel)[0][19999], vec!["Row 19999", "19999", "Some text to compress"]);
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        assert!(workbook.set_compression_threads(0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cell_xml() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row!["a <b> & 'c' \"d\"", 10.3, 1000.0, -2.5e20, true])?;
+            let mut row = Row::new();
+            row.add_cell_at("AB", "far".into())?;
+            sheet_writer.write_row_at(5, row)
+        })?;
+        workbook.finish()?;
+        let sheet = read_part(&cursor, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains(concat!(
+            "<row r=\"1\">\n",
+            "<c r=\"A1\" t=\"str\"><v>a &lt;b&gt; &amp; &apos;c&apos; &quot;d&quot;</v></c>\n",
+            "<c r=\"B1\"><v>10.3</v></c>\n",
+            "<c r=\"C1\"><v>1000</v></c>\n",
+            "<c r=\"D1\"><v>-2.5e20</v></c>\n",
+            "<c r=\"E1\" t=\"b\"><v>1</v></c>\n",
+            "\n</row>\n",
+        )));
+        assert!(sheet.contains("<c r=\"AB5\" t=\"str\"><v>far</v></c>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_first() -> IoResult<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut workbook = WorkBook::new(&mut cursor)?;
+        let style = workbook.create_cell_style((255, 255, 255), (0, 0, 0));
+        workbook.define_local_name("Sheet 2", "_xlnm.Print_Area", "'Sheet 2'!$A$1:$B$2")?;
+        workbook.write_metadata_first(2)?;
+        assert!(workbook.define_name("Late", "1").is_err());
+        assert!(workbook.set_properties(DocProperties::new().title("Late")).is_err());
+        assert!(workbook.set_protection(WorkbookProtection::new()).is_err());
+        assert!(workbook.write_metadata_first(2).is_err());
+        workbook.get_new_sheet().write_sheet(|sheet_writer| {
+            sheet_writer.write_row(row![("Header", &style)])?;
+            sheet_writer.write_row(row![1.0])
+        })?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| sheet_writer.write_row(row!["Second"]))?;
+        workbook.finish()?;
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(cursor.get_ref().clone())).unwrap();
+        let names = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect::<Vec<String>>();
+        let position = |name: &str| names.iter().position(|n| n == name).unwrap();
+        assert_eq!(names[0], "[Content_Types].xml");
+        for part in ["xl/workbook.xml", "xl/styles.xml", "xl/sharedStrings.xml"].iter() {
+            assert!(position(part) < position("xl/worksheets/sheet1.xml"));
+        }
+        assert!(position("xl/worksheets/sheet1.xml") < position("xl/worksheets/sheet2.xml"));
+        let book = read_part(&cursor, "xl/workbook.xml");
+        assert!(book.contains("<sheet name=\"Sheet 2\" sheetId=\"2\" r:id=\"rId4\"/>"));
+        assert!(book.contains("_xlnm.Print_Area"));
+        assert!(read_part(&cursor, "xl/styles.xml").contains("<cellXfs count=\"3\">"));
+        assert_eq!(xlsx_to_vec(cursor), vec![vec![vec!["Header"], vec!["1"]], vec![vec!["Second"]]]);
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        workbook.write_metadata_first(2)?;
+        workbook.get_new_sheet().write_sheet(|sheet_writer| sheet_writer.write_row(row!["Only one"]))?;
+        assert!(workbook.finish().is_err());
+
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()))?;
+        workbook.write_metadata_first(1)?;
+        let style = workbook.create_cell_style((255, 255, 255), (0, 0, 0));
+        workbook.get_new_sheet().write_sheet(|sheet_writer| sheet_writer.write_row(row![("Late", &style)]))?;
+        assert!(workbook.finish().is_err());
+        Ok(())
+    }
+
+    fn read_part_bytes(cursor: &Cursor<Vec<u8>>, name: &str) -> Vec<u8> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(cursor.get_ref().clone())).unwrap();
+        let mut content = Vec::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn read_part(cursor: &Cursor<Vec<u8>>, name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(cursor.get_ref().clone())).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn part_exists(cursor: &Cursor<Vec<u8>>, name: &str) -> bool {
+        let mut archive = zip::ZipArchive::new(Cursor::new(cursor.get_ref().clone())).unwrap();
+        let exists = archive.by_name(name).is_ok();
+        exists
+    }
+
     fn xlsx_to_vec(cursor: Cursor<Vec<u8>>) -> Vec<Vec<Vec<String>>> {
         let mut xlsx_reader: Xlsx<_> = open_workbook_from_rs(cursor).unwrap();
         let mut result = (1..5)
-            .into_iter()
             .map(
                 |sheet_n| match xlsx_reader.worksheet_range(&format!("Sheet {}", sheet_n)) {
                     Some(result_calamine) => result_calamine
                         .unwrap()
                         .rows()
-                        .map(|row| row.into_iter().map(|column| column.to_string()).collect())
+                        .map(|row| row.iter().map(|column| column.to_string()).collect())
                         .collect::<Vec<Vec<String>>>(),
                     None => Vec::new(),
                 },
@@ -0,0 +1,545 @@
+use crate::excel::deflate::ParallelDeflater;
+use crc32fast::Hasher;
+use flate2::{write::DeflateEncoder, Compression};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+/// The general purpose flag telling the CRC and the sizes are in a data descriptor after the data.
+const DATA_DESCRIPTOR_FLAG: u16 = 0x0008;
+const STORED_METHOD: u16 = 0;
+const DEFLATE_METHOD: u16 = 8;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// 1980-01-01, the first date of the MS-DOS format, as the parts don't have a modification date.
+const DOS_DATE: u16 = (1 << 5) | 1;
+const LOCAL_FILE_HEADER_LENGTH: u64 = 30;
+
+/// How the parts of the package are compressed. Set it with [WorkBook::set_compression_method](crate::excel::WorkBook::set_compression_method).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionMethod {
+    /// The parts are not compressed, the fastest but the biggest.
+    Stored,
+    /// The parts are compressed with deflate, as by default.
+    Deflated,
+}
+
+/// The deflate level used unless another one is set.
+pub(crate) const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+pub(crate) const MAX_COMPRESSION_LEVEL: u32 = 9;
+
+/// Writes the parts of the XLSX package as a ZIP archive, compressing them while they are written.
+///
+/// When the output can seek, the sizes of every part are written in its header once the part is finished. Otherwise they follow the part in a data descriptor, so the package can be streamed into a pipe or an HTTP response.
+///
+/// ZIP64 is only used by the parts that end up bigger than 4 GiB, or that start after 4 GiB, as Go's `archive/zip` does: their sizes are in a ZIP64 data descriptor and in the central directory, even when the output can seek.
+/// So nothing needs to be known before writing a part, and small files stay without any ZIP64 record.
+pub struct PackageWriter<W>
+where
+    W: Write,
+{
+    output: Output<W>,
+    /// Seeks the output, only when it supports it, so the headers of the parts can be patched.
+    seek: Option<fn(&mut W, SeekFrom) -> IoResult<u64>>,
+    /// Where the package starts in a seekable output.
+    start: u64,
+    entries: Vec<Entry>,
+    current: Option<(Entry, Hasher)>,
+    /// Bytes reserved in the stored part being written, to be filled at its end, as their offset in the package, the CRC of the part before them and their length.
+    reserved: Option<(u64, Hasher, usize)>,
+    method: CompressionMethod,
+    level: u32,
+    threads: usize,
+}
+
+/// The output, owned by the compressor while a part is written.
+enum Output<W: Write> {
+    Idle(CountingWriter<W>),
+    Storing(CountingWriter<W>),
+    Deflating(DeflateEncoder<CountingWriter<W>>),
+    ParallelDeflating(ParallelDeflater<CountingWriter<W>>),
+    Finished,
+}
+
+/// Counts the bytes written in the package, to know the offsets and the compressed sizes.
+struct CountingWriter<W> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = self.writer.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.flush()
+    }
+}
+
+/// A part of the package, as listed in the central directory.
+#[derive(Clone, Debug)]
+pub(crate) struct Entry {
+    name: String,
+    header_offset: u64,
+    method: CompressionMethod,
+    /// Whether the CRC and the sizes follow the data, instead of being in the local header.
+    data_descriptor: bool,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+}
+
+impl Entry {
+    fn flags(&self) -> u16 {
+        if self.data_descriptor {
+            DATA_DESCRIPTOR_FLAG
+        } else {
+            0
+        }
+    }
+
+    fn method(&self) -> u16 {
+        match self.method {
+            CompressionMethod::Stored => STORED_METHOD,
+            CompressionMethod::Deflated => DEFLATE_METHOD,
+        }
+    }
+
+    /// Whether the sizes don't fit in 32 bits, so they are written as ZIP64.
+    fn large_sizes(&self) -> bool {
+        self.size >= u32::MAX as u64 || self.compressed_size >= u32::MAX as u64
+    }
+
+    fn large_offset(&self) -> bool {
+        self.header_offset >= u32::MAX as u64
+    }
+
+    fn version(&self) -> u16 {
+        if self.large_sizes() || self.large_offset() {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        }
+    }
+
+    /// Where the compressed data starts, after the local header.
+    pub(crate) fn data_offset(&self) -> u64 {
+        self.header_offset + LOCAL_FILE_HEADER_LENGTH + self.name.len() as u64
+    }
+
+    /// The local header, with the CRC and the sizes unless they are in a data descriptor.
+    fn local_header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(LOCAL_FILE_HEADER_LENGTH as usize + self.name.len());
+        put_u32(&mut header, LOCAL_FILE_HEADER_SIGNATURE);
+        put_u16(&mut header, self.version());
+        put_u16(&mut header, self.flags());
+        put_u16(&mut header, self.method());
+        put_u16(&mut header, 0);
+        put_u16(&mut header, DOS_DATE);
+        if self.data_descriptor {
+            put_u32(&mut header, 0);
+            put_u32(&mut header, 0);
+            put_u32(&mut header, 0);
+        } else {
+            put_u32(&mut header, self.crc32);
+            put_u32(&mut header, self.compressed_size as u32);
+            put_u32(&mut header, self.size as u32);
+        }
+        put_u16(&mut header, self.name.len() as u16);
+        put_u16(&mut header, 0);
+        header.extend_from_slice(self.name.as_bytes());
+        header
+    }
+
+    fn data_descriptor(&self) -> Vec<u8> {
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, self.crc32);
+        if self.large_sizes() {
+            put_u64(&mut descriptor, self.compressed_size);
+            put_u64(&mut descriptor, self.size);
+        } else {
+            put_u32(&mut descriptor, self.compressed_size as u32);
+            put_u32(&mut descriptor, self.size as u32);
+        }
+        descriptor
+    }
+}
+
+impl<W> PackageWriter<W>
+where
+    W: Write,
+{
+    /// A package written into a seekable output, with the sizes of the parts in their headers.
+    pub(crate) fn new(mut writer: W) -> IoResult<Self>
+    where
+        W: Seek,
+    {
+        let start = writer.stream_position()?;
+        Ok(Self::with_seek(writer, Some(W::seek), start))
+    }
+
+    /// A package written into an output that can't seek, with the sizes of the parts in data descriptors.
+    pub(crate) fn new_streaming(writer: W) -> Self {
+        Self::with_seek(writer, None, 0)
+    }
+
+    fn with_seek(writer: W, seek: Option<fn(&mut W, SeekFrom) -> IoResult<u64>>, start: u64) -> Self {
+        Self {
+            output: Output::Idle(CountingWriter { writer, count: 0 }),
+            seek,
+            start,
+            entries: Vec::new(),
+            current: None,
+            reserved: None,
+            method: CompressionMethod::Deflated,
+            level: DEFAULT_COMPRESSION_LEVEL,
+            threads: 1,
+        }
+    }
+
+    /// Sets how the next parts are compressed.
+    pub(crate) fn set_compression_method(&mut self, method: CompressionMethod) {
+        self.method = method;
+    }
+
+    /// Sets the deflate level of the next parts.
+    pub(crate) fn set_compression_level(&mut self, level: u32) {
+        self.level = level;
+    }
+
+    /// Sets how many threads deflate each of the next parts.
+    pub(crate) fn set_compression_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    /// Finishes the part being written, if any, and starts a new one.
+    pub(crate) fn start_file(&mut self, name: impl Into<String>) -> IoResult<()> {
+        self.finish_file()?;
+        let output = idle(&mut self.output)?;
+        let entry = Entry {
+            name: name.into(),
+            header_offset: output.count,
+            method: self.method,
+            data_descriptor: self.seek.is_none(),
+            crc32: 0,
+            compressed_size: 0,
+            size: 0,
+        };
+        // The CRC and the sizes are patched or written in the data descriptor once the part is finished.
+        output.write_all(&entry.local_header())?;
+        let output = match std::mem::replace(&mut self.output, Output::Finished) {
+            Output::Idle(output) => output,
+            _ => unreachable!("the output is idle between parts"),
+        };
+        self.output = match self.method {
+            CompressionMethod::Stored => Output::Storing(output),
+            CompressionMethod::Deflated if self.threads > 1 => {
+                Output::ParallelDeflating(ParallelDeflater::new(output, self.level, self.threads))
+            }
+            CompressionMethod::Deflated => Output::Deflating(DeflateEncoder::new(output, Compression::new(self.level))),
+        };
+        self.current = Some((entry, Hasher::new()));
+        Ok(())
+    }
+
+    /// Writes a placeholder in the part being written, to be replaced by [fill_reserved](PackageWriter::fill_reserved) with bytes only known at its end, like the `<dimension>` of a sheet.
+    /// It is only possible when the part is stored into a seekable output, as the compressed data can't be changed. Gives back the offset of the placeholder, or `None` when it was not written.
+    pub(crate) fn reserve(&mut self, placeholder: &[u8]) -> IoResult<Option<u64>> {
+        let (entry, hasher) = match (&mut self.current, &mut self.output) {
+            (Some(current), Output::Storing(_)) if self.seek.is_some() && self.reserved.is_none() => current,
+            _ => return Ok(None),
+        };
+        let offset = match &mut self.output {
+            Output::Storing(output) => {
+                let offset = output.count;
+                output.write_all(placeholder)?;
+                offset
+            }
+            _ => unreachable!("only stored parts are reserved"),
+        };
+        entry.size += placeholder.len() as u64;
+        // The CRC of the rest of the part is computed apart, to combine it with the bytes that replace the placeholder.
+        let prefix = std::mem::replace(hasher, Hasher::new());
+        self.reserved = Some((offset, prefix, placeholder.len()));
+        Ok(Some(offset))
+    }
+
+    /// Replaces the placeholder [reserved](PackageWriter::reserve) in the part being written with bytes of the same length.
+    pub(crate) fn fill_reserved(&mut self, offset: u64, bytes: &[u8]) -> IoResult<()> {
+        let (seek, output) = match (self.seek, &mut self.output) {
+            (Some(seek), Output::Storing(output)) => (seek, output),
+            _ => return Err(Error::other("nothing was reserved in the part")),
+        };
+        match &self.reserved {
+            Some((reserved, _, length)) if *reserved == offset && *length == bytes.len() => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the bytes don't match the placeholder reserved",
+                ))
+            }
+        }
+        let end = output.count;
+        seek(&mut output.writer, SeekFrom::Start(self.start + offset))?;
+        output.writer.write_all(bytes)?;
+        seek(&mut output.writer, SeekFrom::Start(self.start + end))?;
+        self.combine_reserved(bytes);
+        Ok(())
+    }
+
+    /// Joins the CRC of the part before the reserved bytes, of the bytes and of the part after them.
+    fn combine_reserved(&mut self, bytes: &[u8]) {
+        if let (Some((_, mut crc, _)), Some((_, hasher))) = (self.reserved.take(), &mut self.current) {
+            crc.update(bytes);
+            crc.combine(hasher);
+            *hasher = crc;
+        }
+    }
+
+    /// Finishes the part being written, writing its sizes.
+    fn finish_file(&mut self) -> IoResult<()> {
+        if let Some((_, _, length)) = &self.reserved {
+            // The placeholder was never filled, so it stays in the part.
+            let placeholder = vec![b' '; *length];
+            self.combine_reserved(&placeholder);
+        }
+        let (mut entry, hasher) = match self.current.take() {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        let output = match std::mem::replace(&mut self.output, Output::Finished) {
+            Output::Storing(output) => output,
+            Output::Deflating(encoder) => encoder.finish()?,
+            Output::ParallelDeflating(deflater) => deflater.finish()?,
+            _ => unreachable!("a part is written while the output is storing or deflating"),
+        };
+        self.output = Output::Idle(output);
+        let output = idle(&mut self.output)?;
+        entry.crc32 = hasher.finalize();
+        entry.compressed_size = output.count - entry.data_offset();
+        if let Some(seek) = self.seek {
+            // A part bigger than 4 GiB has no room for its ZIP64 sizes in the local header, so they go in a data descriptor, as when streaming.
+            entry.data_descriptor = entry.large_sizes();
+            let end = output.count;
+            seek(&mut output.writer, SeekFrom::Start(self.start + entry.header_offset))?;
+            output.writer.write_all(&entry.local_header())?;
+            seek(&mut output.writer, SeekFrom::Start(self.start + end))?;
+        }
+        if entry.data_descriptor {
+            output.write_all(&entry.data_descriptor())?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Copies a part already compressed by another package, reading its data from the output of that package.
+    /// As the sizes are known, they are written in the local header, even when streaming.
+    pub(crate) fn write_compressed_part(&mut self, part: &Entry, data: &mut impl Read) -> IoResult<()> {
+        self.finish_file()?;
+        let output = idle(&mut self.output)?;
+        let entry = Entry {
+            header_offset: output.count,
+            data_descriptor: part.large_sizes(),
+            ..part.clone()
+        };
+        output.write_all(&entry.local_header())?;
+        let copied = std::io::copy(&mut data.take(entry.compressed_size), output)?;
+        if copied != entry.compressed_size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("the compressed part {} is incomplete", entry.name),
+            ));
+        }
+        if entry.data_descriptor {
+            output.write_all(&entry.data_descriptor())?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// How the next parts are compressed, as the method and the deflate level.
+    pub(crate) fn compression(&self) -> (CompressionMethod, u32) {
+        (self.method, self.level)
+    }
+
+    /// Finishes the last part without writing the central directory, giving back the output and the parts written in it, to copy them into another package.
+    pub(crate) fn finish_parts(mut self) -> IoResult<(W, Vec<Entry>)> {
+        self.finish_file()?;
+        match std::mem::replace(&mut self.output, Output::Finished) {
+            Output::Idle(output) => Ok((output.writer, std::mem::take(&mut self.entries))),
+            _ => Err(Error::other("the package is already finished")),
+        }
+    }
+
+    /// Finishes the last part and writes the central directory, giving back the output.
+    pub(crate) fn finish(mut self) -> IoResult<W> {
+        self.finish_file()?;
+        let output = idle(&mut self.output)?;
+        let directory_offset = output.count;
+        let mut directory = Vec::new();
+        for entry in self.entries.iter() {
+            let large_offset = entry.large_offset();
+            let mut extra = Vec::new();
+            if entry.large_sizes() {
+                put_u64(&mut extra, entry.size);
+                put_u64(&mut extra, entry.compressed_size);
+            }
+            if large_offset {
+                put_u64(&mut extra, entry.header_offset);
+            }
+            put_u32(&mut directory, CENTRAL_DIRECTORY_HEADER_SIGNATURE);
+            put_u16(&mut directory, VERSION_ZIP64);
+            put_u16(&mut directory, entry.version());
+            put_u16(&mut directory, entry.flags());
+            put_u16(&mut directory, entry.method());
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, DOS_DATE);
+            put_u32(&mut directory, entry.crc32);
+            if entry.large_sizes() {
+                put_u32(&mut directory, u32::MAX);
+                put_u32(&mut directory, u32::MAX);
+            } else {
+                put_u32(&mut directory, entry.compressed_size as u32);
+                put_u32(&mut directory, entry.size as u32);
+            }
+            put_u16(&mut directory, entry.name.len() as u16);
+            put_u16(&mut directory, if extra.is_empty() { 0 } else { extra.len() as u16 + 4 });
+            // The comment length, the disk number and the internal and external attributes.
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u32(&mut directory, 0);
+            put_u32(&mut directory, if large_offset { u32::MAX } else { entry.header_offset as u32 });
+            directory.extend_from_slice(entry.name.as_bytes());
+            if !extra.is_empty() {
+                put_u16(&mut directory, ZIP64_EXTRA_FIELD_ID);
+                put_u16(&mut directory, extra.len() as u16);
+                directory.extend_from_slice(&extra);
+            }
+        }
+        let directory_size = directory.len() as u64;
+        let entries = self.entries.len() as u64;
+        if entries >= u16::MAX as u64 || directory_offset >= u32::MAX as u64 || directory_size >= u32::MAX as u64 {
+            let zip64_offset = directory_offset + directory_size;
+            put_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+            put_u64(&mut directory, 44);
+            put_u16(&mut directory, VERSION_ZIP64);
+            put_u16(&mut directory, VERSION_ZIP64);
+            put_u32(&mut directory, 0);
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, entries);
+            put_u64(&mut directory, entries);
+            put_u64(&mut directory, directory_size);
+            put_u64(&mut directory, directory_offset);
+            put_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, zip64_offset);
+            put_u32(&mut directory, 1);
+        }
+        put_u32(&mut directory, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, entries.min(u16::MAX as u64) as u16);
+        put_u16(&mut directory, entries.min(u16::MAX as u64) as u16);
+        put_u32(&mut directory, directory_size.min(u32::MAX as u64) as u32);
+        put_u32(&mut directory, directory_offset.min(u32::MAX as u64) as u32);
+        put_u16(&mut directory, 0);
+        output.write_all(&directory)?;
+        output.flush()?;
+        match std::mem::replace(&mut self.output, Output::Finished) {
+            Output::Idle(output) => Ok(output.writer),
+            _ => unreachable!("the output is idle after the last part"),
+        }
+    }
+}
+
+impl<W> Write for PackageWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let (entry, hasher) = match &mut self.current {
+            Some(current) => current,
+            None => return Err(Error::other("no part of the package was started")),
+        };
+        let written = match &mut self.output {
+            Output::Storing(output) => output.write(buf)?,
+            Output::Deflating(encoder) => encoder.write(buf)?,
+            Output::ParallelDeflating(deflater) => deflater.write(buf)?,
+            _ => unreachable!("a part is written while the output is storing or deflating"),
+        };
+        hasher.update(&buf[..written]);
+        entry.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match &mut self.output {
+            Output::Idle(output) | Output::Storing(output) => output.flush(),
+            Output::Deflating(encoder) => encoder.flush(),
+            Output::ParallelDeflating(deflater) => deflater.flush(),
+            Output::Finished => Ok(()),
+        }
+    }
+}
+
+/// A file in the temporary directory, removed when it is dropped, where a part is compressed before it is copied into the package.
+pub(crate) struct TemporaryFile {
+    path: PathBuf,
+    pub(crate) file: File,
+}
+
+impl TemporaryFile {
+    pub(crate) fn new() -> IoResult<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "fastexcel-{}-{}.part",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { path, file })
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The output between parts, when nothing is being compressed.
+fn idle<W: Write>(output: &mut Output<W>) -> IoResult<&mut CountingWriter<W>> {
+    match output {
+        Output::Idle(output) => Ok(output),
+        _ => Err(Error::other("the package is already finished")),
+    }
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,223 @@
+use crate::excel::row::{absolute_range, quote_sheet_name, MAX_ROWS};
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// The page orientation when printing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// The paper size when printing. `Other` takes any paper code of the spreadsheetml spec.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaperSize {
+    Letter,
+    Legal,
+    A3,
+    A4,
+    A5,
+    Other(u32),
+}
+
+/// The page margins, in inches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageMargins {
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
+    pub header: f64,
+    pub footer: f64,
+}
+
+impl Default for PageMargins {
+    /// The Excel "Normal" margins.
+    fn default() -> Self {
+        Self {
+            left: 0.7,
+            right: 0.7,
+            top: 0.75,
+            bottom: 0.75,
+            header: 0.3,
+            footer: 0.3,
+        }
+    }
+}
+
+/// How a sheet is printed. Set it with [SheetWriter::set_page_setup](crate::excel::SheetWriter::set_page_setup).
+#[derive(Clone, Debug, Default)]
+pub struct PageSetup {
+    orientation: Option<Orientation>,
+    paper_size: Option<PaperSize>,
+    scale: Option<u32>,
+    fit_to_pages: Option<(u32, u32)>,
+    margins: PageMargins,
+    center_horizontally: bool,
+    center_vertically: bool,
+    print_gridlines: bool,
+    print_headings: bool,
+    print_area: Option<String>,
+    repeat_rows: Option<(usize, usize)>,
+}
+
+impl PageSetup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    pub fn paper_size(mut self, paper_size: PaperSize) -> Self {
+        self.paper_size = Some(paper_size);
+        self
+    }
+
+    /// Scales the printed sheet, from 10 to 400 percent.
+    pub fn scale(mut self, percent: u32) -> Self {
+        self.scale = Some(percent);
+        self
+    }
+
+    /// Shrinks the printed sheet to the given number of pages wide and tall. Use 0 to not limit a direction.
+    /// It needs to be set before writing the first row.
+    pub fn fit_to_pages(mut self, width: u32, height: u32) -> Self {
+        self.fit_to_pages = Some((width, height));
+        self
+    }
+
+    pub fn margins(mut self, margins: PageMargins) -> Self {
+        self.margins = margins;
+        self
+    }
+
+    pub fn center_horizontally(mut self, center: bool) -> Self {
+        self.center_horizontally = center;
+        self
+    }
+
+    pub fn center_vertically(mut self, center: bool) -> Self {
+        self.center_vertically = center;
+        self
+    }
+
+    pub fn print_gridlines(mut self, print: bool) -> Self {
+        self.print_gridlines = print;
+        self
+    }
+
+    /// Prints the row numbers and the column letters.
+    pub fn print_headings(mut self, print: bool) -> Self {
+        self.print_headings = print;
+        self
+    }
+
+    /// Prints only the given range, like `A1:H50`.
+    pub fn print_area(mut self, range: impl Into<String>) -> Self {
+        self.print_area = Some(range.into());
+        self
+    }
+
+    /// Repeats the rows from `first_row` to `last_row` at the top of every printed page.
+    pub fn repeat_rows(mut self, first_row: usize, last_row: usize) -> Self {
+        self.repeat_rows = Some((first_row, last_row));
+        self
+    }
+
+    pub(crate) fn fits_to_page(&self) -> bool {
+        self.fit_to_pages.is_some()
+    }
+
+    pub(crate) fn validate(&self) -> IoResult<()> {
+        if let Some(scale) = self.scale {
+            if !(10..=400).contains(&scale) {
+                return Err(invalid_setup("the scale must be between 10 and 400"));
+            }
+        }
+        if let Some(range) = &self.print_area {
+            if absolute_range("", range).is_none() {
+                return Err(invalid_setup(&format!("invalid print area {}", range)));
+            }
+        }
+        if let Some((first_row, last_row)) = self.repeat_rows {
+            if first_row == 0 || first_row > last_row || last_row > MAX_ROWS {
+                return Err(invalid_setup("invalid rows to repeat"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The built-in defined names needed by this setup, as `(name, formula)`.
+    pub(crate) fn defined_names(&self, sheet_name: &str) -> Vec<(&'static str, String)> {
+        let mut names = Vec::new();
+        if let Some(range) = &self.print_area {
+            if let Some(formula) = absolute_range(sheet_name, range) {
+                names.push(("_xlnm.Print_Area", formula));
+            }
+        }
+        if let Some((first_row, last_row)) = self.repeat_rows {
+            names.push((
+                "_xlnm.Print_Titles",
+                format!("{}!${}:${}", quote_sheet_name(sheet_name), first_row, last_row),
+            ));
+        }
+        names
+    }
+
+    /// Writes `<printOptions>`, `<pageMargins>` and `<pageSetup>`, in the order of the sheet footer.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        let print_options = [
+            ("horizontalCentered", self.center_horizontally),
+            ("verticalCentered", self.center_vertically),
+            ("headings", self.print_headings),
+            ("gridLines", self.print_gridlines),
+        ];
+        if print_options.iter().any(|(_, enabled)| *enabled) {
+            write!(writer, "<printOptions")?;
+            for (name, enabled) in print_options.iter() {
+                if *enabled {
+                    write!(writer, " {}=\"1\"", name)?;
+                }
+            }
+            writeln!(writer, "/>")?;
+        }
+        let margins = &self.margins;
+        writeln!(
+            writer,
+            "<pageMargins left=\"{}\" right=\"{}\" top=\"{}\" bottom=\"{}\" header=\"{}\" footer=\"{}\"/>",
+            margins.left, margins.right, margins.top, margins.bottom, margins.header, margins.footer
+        )?;
+        write!(writer, "<pageSetup")?;
+        if let Some(paper_size) = self.paper_size {
+            let code = match paper_size {
+                PaperSize::Letter => 1,
+                PaperSize::Legal => 5,
+                PaperSize::A3 => 8,
+                PaperSize::A4 => 9,
+                PaperSize::A5 => 11,
+                PaperSize::Other(code) => code,
+            };
+            write!(writer, " paperSize=\"{}\"", code)?;
+        }
+        if let Some(scale) = self.scale {
+            write!(writer, " scale=\"{}\"", scale)?;
+        }
+        if let Some((width, height)) = self.fit_to_pages {
+            write!(writer, " fitToWidth=\"{}\" fitToHeight=\"{}\"", width, height)?;
+        }
+        if let Some(orientation) = self.orientation {
+            let orientation = match orientation {
+                Orientation::Portrait => "portrait",
+                Orientation::Landscape => "landscape",
+            };
+            write!(writer, " orientation=\"{}\"", orientation)?;
+        }
+        writeln!(writer, "/>")
+    }
+}
+
+fn invalid_setup(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,339 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha512};
+use std::io::{Error, Result as IoResult, Write};
+
+/// The iterations Excel uses to hash a password, to protect a sheet or to encrypt the workbook.
+pub(crate) const SPIN_COUNT: u32 = 100_000;
+
+/// Where the iteration number is hashed with the previous hash, as protection and encryption don't agree on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IteratorOrder {
+    /// Before the hash, as the ECMA-376 Agile Encryption does.
+    First,
+    /// After the hash, as the protection of sheets and workbooks does.
+    Last,
+}
+
+/// Hashes the UTF-16 password with SHA-512 and the salt, then hashes it again `spin_count` times with the iteration number.
+pub(crate) fn hash_password(password: &str, salt: &[u8], spin_count: u32, order: IteratorOrder) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(salt);
+    for unit in password.encode_utf16() {
+        hasher.update(unit.to_le_bytes());
+    }
+    let mut hash = hasher.finalize();
+    for iterator in 0..spin_count {
+        let mut hasher = Sha512::new();
+        if order == IteratorOrder::First {
+            hasher.update(iterator.to_le_bytes());
+        }
+        hasher.update(hash);
+        if order == IteratorOrder::Last {
+            hasher.update(iterator.to_le_bytes());
+        }
+        hash = hasher.finalize();
+    }
+    hash.to_vec()
+}
+
+/// A password hashed as Excel does: SHA-512 over a random salt and the UTF-16 password, then hashed again `spinCount` times.
+#[derive(Clone, Debug)]
+pub(crate) struct PasswordHash {
+    hash: String,
+    salt: String,
+    spin_count: u32,
+}
+
+impl PasswordHash {
+    pub(crate) fn new(password: &str) -> IoResult<Self> {
+        let mut salt = [0u8; 16];
+        getrandom::getrandom(&mut salt).map_err(|err| Error::other(err.to_string()))?;
+        Ok(Self::with_salt(password, &salt, SPIN_COUNT))
+    }
+
+    pub(crate) fn with_salt(password: &str, salt: &[u8], spin_count: u32) -> Self {
+        let hash = hash_password(password, salt, spin_count, IteratorOrder::Last);
+        Self {
+            hash: STANDARD.encode(hash),
+            salt: STANDARD.encode(salt),
+            spin_count,
+        }
+    }
+
+    /// Writes the attributes of the password of a protection element, like `algorithmName` or, with the `workbook` prefix, `workbookAlgorithmName`.
+    pub(crate) fn write_attributes(&self, writer: &mut impl Write, prefix: &str) -> IoResult<()> {
+        let spin_count = self.spin_count.to_string();
+        let attributes = [
+            ("AlgorithmName", "SHA-512"),
+            ("HashValue", self.hash.as_str()),
+            ("SaltValue", self.salt.as_str()),
+            ("SpinCount", spin_count.as_str()),
+        ];
+        for (name, value) in attributes.iter() {
+            if prefix.is_empty() {
+                write!(writer, " {}{}=\"{}\"", name[..1].to_ascii_lowercase(), &name[1..], value)?;
+            } else {
+                write!(writer, " {}{}=\"{}\"", prefix, name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The protection of the cells of a style, applied when their sheet is [protected](crate::excel::Sheet::set_protection). Use it with [WorkBook::create_cell_style_with_protection](crate::excel::WorkBook::create_cell_style_with_protection).
+#[derive(Clone, Copy, Debug)]
+pub struct CellProtection {
+    pub(crate) locked: bool,
+    pub(crate) hidden: bool,
+}
+
+impl Default for CellProtection {
+    /// The protection Excel gives to cells by default: locked, with their formulas visible.
+    fn default() -> Self {
+        Self {
+            locked: true,
+            hidden: false,
+        }
+    }
+}
+
+impl CellProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the cells can't be edited. Unlock the cells users should still fill in.
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// Whether the formulas of the cells are hidden.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+}
+
+/// Protects a sheet from changes. Set it with [Sheet::set_protection](crate::excel::Sheet::set_protection).
+///
+/// Only the cells with a style that is not locked can be edited. The other actions are not allowed unless they are enabled here.
+#[derive(Clone, Debug)]
+pub struct SheetProtection {
+    password: Option<PasswordHash>,
+    edit_objects: bool,
+    edit_scenarios: bool,
+    format_cells: bool,
+    format_columns: bool,
+    format_rows: bool,
+    insert_columns: bool,
+    insert_rows: bool,
+    insert_hyperlinks: bool,
+    delete_columns: bool,
+    delete_rows: bool,
+    select_locked_cells: bool,
+    select_unlocked_cells: bool,
+    sort: bool,
+    auto_filter: bool,
+    pivot_tables: bool,
+}
+
+impl Default for SheetProtection {
+    /// The protection Excel sets by default: only selecting cells is allowed.
+    fn default() -> Self {
+        Self {
+            password: None,
+            edit_objects: false,
+            edit_scenarios: false,
+            format_cells: false,
+            format_columns: false,
+            format_rows: false,
+            insert_columns: false,
+            insert_rows: false,
+            insert_hyperlinks: false,
+            delete_columns: false,
+            delete_rows: false,
+            select_locked_cells: true,
+            select_unlocked_cells: true,
+            sort: false,
+            auto_filter: false,
+            pivot_tables: false,
+        }
+    }
+}
+
+impl SheetProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a password to unprotect the sheet. Only its salted hash is kept.
+    pub fn password(mut self, password: &str) -> IoResult<Self> {
+        self.password = Some(PasswordHash::new(password)?);
+        Ok(self)
+    }
+
+    pub fn edit_objects(mut self, allow: bool) -> Self {
+        self.edit_objects = allow;
+        self
+    }
+
+    pub fn edit_scenarios(mut self, allow: bool) -> Self {
+        self.edit_scenarios = allow;
+        self
+    }
+
+    pub fn format_cells(mut self, allow: bool) -> Self {
+        self.format_cells = allow;
+        self
+    }
+
+    pub fn format_columns(mut self, allow: bool) -> Self {
+        self.format_columns = allow;
+        self
+    }
+
+    pub fn format_rows(mut self, allow: bool) -> Self {
+        self.format_rows = allow;
+        self
+    }
+
+    pub fn insert_columns(mut self, allow: bool) -> Self {
+        self.insert_columns = allow;
+        self
+    }
+
+    pub fn insert_rows(mut self, allow: bool) -> Self {
+        self.insert_rows = allow;
+        self
+    }
+
+    pub fn insert_hyperlinks(mut self, allow: bool) -> Self {
+        self.insert_hyperlinks = allow;
+        self
+    }
+
+    pub fn delete_columns(mut self, allow: bool) -> Self {
+        self.delete_columns = allow;
+        self
+    }
+
+    pub fn delete_rows(mut self, allow: bool) -> Self {
+        self.delete_rows = allow;
+        self
+    }
+
+    pub fn select_locked_cells(mut self, allow: bool) -> Self {
+        self.select_locked_cells = allow;
+        self
+    }
+
+    pub fn select_unlocked_cells(mut self, allow: bool) -> Self {
+        self.select_unlocked_cells = allow;
+        self
+    }
+
+    pub fn sort(mut self, allow: bool) -> Self {
+        self.sort = allow;
+        self
+    }
+
+    pub fn auto_filter(mut self, allow: bool) -> Self {
+        self.auto_filter = allow;
+        self
+    }
+
+    pub fn pivot_tables(mut self, allow: bool) -> Self {
+        self.pivot_tables = allow;
+        self
+    }
+
+    /// Writes `<sheetProtection>`. Its attributes are set when the action is protected, so the allowed flags are inverted.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(writer, "<sheetProtection")?;
+        if let Some(password) = &self.password {
+            password.write_attributes(writer, "")?;
+        }
+        write!(writer, " sheet=\"1\"")?;
+        // The actions Excel protects by default are only written when they are allowed, and the other way around.
+        let flags = [
+            ("objects", !self.edit_objects, false),
+            ("scenarios", !self.edit_scenarios, false),
+            ("formatCells", !self.format_cells, true),
+            ("formatColumns", !self.format_columns, true),
+            ("formatRows", !self.format_rows, true),
+            ("insertColumns", !self.insert_columns, true),
+            ("insertRows", !self.insert_rows, true),
+            ("insertHyperlinks", !self.insert_hyperlinks, true),
+            ("deleteColumns", !self.delete_columns, true),
+            ("deleteRows", !self.delete_rows, true),
+            ("selectLockedCells", !self.select_locked_cells, false),
+            ("sort", !self.sort, true),
+            ("autoFilter", !self.auto_filter, true),
+            ("pivotTables", !self.pivot_tables, true),
+            ("selectUnlockedCells", !self.select_unlocked_cells, false),
+        ];
+        for (name, protected, default) in flags.iter() {
+            if protected != default {
+                write!(writer, " {}=\"{}\"", name, if *protected { 1 } else { 0 })?;
+            }
+        }
+        writeln!(writer, "/>")
+    }
+}
+
+/// Protects the structure of a workbook, so sheets can't be added, deleted, renamed, moved or hidden. Set it with [WorkBook::set_protection](crate::excel::WorkBook::set_protection).
+#[derive(Clone, Debug)]
+pub struct WorkbookProtection {
+    password: Option<PasswordHash>,
+    lock_structure: bool,
+    lock_windows: bool,
+}
+
+impl Default for WorkbookProtection {
+    fn default() -> Self {
+        Self {
+            password: None,
+            lock_structure: true,
+            lock_windows: false,
+        }
+    }
+}
+
+impl WorkbookProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a password to unprotect the workbook. Only its salted hash is kept.
+    pub fn password(mut self, password: &str) -> IoResult<Self> {
+        self.password = Some(PasswordHash::new(password)?);
+        Ok(self)
+    }
+
+    /// Locks the sheets of the workbook. It is enabled by default.
+    pub fn lock_structure(mut self, lock: bool) -> Self {
+        self.lock_structure = lock;
+        self
+    }
+
+    /// Locks the size and position of the workbook windows.
+    pub fn lock_windows(mut self, lock: bool) -> Self {
+        self.lock_windows = lock;
+        self
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(writer, "<workbookProtection")?;
+        if let Some(password) = &self.password {
+            password.write_attributes(writer, "workbook")?;
+        }
+        if self.lock_structure {
+            write!(writer, " lockStructure=\"1\"")?;
+        }
+        if self.lock_windows {
+            write!(writer, " lockWindows=\"1\"")?;
+        }
+        writeln!(writer, "/>")
+    }
+}
@@ -0,0 +1,68 @@
+use crate::excel::row::escape_xml;
+use std::io::{Result as IoResult, Write};
+
+pub(crate) const HYPERLINK: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink";
+pub(crate) const COMMENTS: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments";
+pub(crate) const CHART: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/chart";
+pub(crate) const DRAWING: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/drawing";
+pub(crate) const IMAGE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image";
+pub(crate) const VML_DRAWING: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/vmlDrawing";
+
+/// The relationships of a single part, written as a `.rels` file next to it.
+#[derive(Default, Debug)]
+pub(crate) struct Relationships {
+    list: Vec<Relationship>,
+}
+
+#[derive(Debug)]
+struct Relationship {
+    kind: &'static str,
+    target: String,
+    external: bool,
+}
+
+impl Relationships {
+    /// Adds a relationship and returns its id number, to be used as `rId{number}`.
+    pub(crate) fn add(&mut self, kind: &'static str, target: String, external: bool) -> usize {
+        self.list.push(Relationship {
+            kind,
+            target,
+            external,
+        });
+        self.list.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> IoResult<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+"#
+        )?;
+        for (i, relationship) in self.list.iter().enumerate() {
+            writeln!(
+                writer,
+                "<Relationship Id=\"rId{}\" Type=\"{}\" Target=\"{}\"{}/>",
+                i + 1,
+                relationship.kind,
+                escape_xml(&relationship.target),
+                if relationship.external {
+                    " TargetMode=\"External\""
+                } else {
+                    ""
+                }
+            )?;
+        }
+        write!(writer, "</Relationships>")
+    }
+}
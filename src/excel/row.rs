@@ -1,12 +1,28 @@
-use crate::excel::CellStyle;
-use std::io::{Result as IoResult, Write};
+use crate::excel::workbook::HYPERLINK_STYLE_ID;
+use crate::excel::{CellStyle, Hyperlink};
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
 
 /// A row of a sheet. You can also create it using the macro `row!`
+///
+/// Besides its cells, a row can have a custom height, be hidden, have a default style for its cells and belong to an outline group.
+/// The cells are placed one after the other starting at column `A`, unless they are added at an explicit column.
 #[derive(Clone, Debug)]
 pub struct Row<'a> {
-    cells: Vec<Cell<'a>>,
+    /// The cells with their zero based column index.
+    cells: Vec<(usize, Cell<'a>)>,
+    height: Option<f64>,
+    hidden: bool,
+    style: Option<&'a CellStyle>,
+    outline_level: u8,
+    collapsed: bool,
 }
 
+/// The deepest outline level Excel supports.
+pub(crate) const MAX_OUTLINE_LEVEL: u8 = 7;
+
+/// The tallest row Excel supports, in points.
+const MAX_ROW_HEIGHT: f64 = 409.0;
+
 /// A Cell of a row. It has a [CellValue](CellValue) and an optional [CellStyle](CellStyle)
 #[derive(Clone, Debug)]
 pub struct Cell<'a> {
@@ -14,71 +30,273 @@ pub struct Cell<'a> {
     style: Option<&'a CellStyle>,
 }
 
+impl<'a> Default for Row<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> Row<'a> {
     pub fn new() -> Self {
-        Self { cells: Vec::new() }
+        Self {
+            cells: Vec::new(),
+            height: None,
+            hidden: false,
+            style: None,
+            outline_level: 0,
+            collapsed: false,
+        }
     }
 
+    /// Adds a cell in the column after the last cell of the row.
     pub fn add_cell(&mut self, cell: Cell<'a>) {
-        self.cells.push(cell);
+        let column_index = self.cells.last().map_or(0, |(index, _)| index + 1);
+        self.cells.push((column_index, cell));
+    }
+
+    /// Adds a cell in a column, like `D`. The columns between the last cell of the row and this one are left empty.
+    pub fn add_cell_at(&mut self, column: &str, cell: Cell<'a>) -> IoResult<()> {
+        if column.is_empty() || !column.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid column {}, it must only have letters, like D", column),
+            ));
+        }
+        let (column_index, _) = parse_cell_ref(&format!("{}1", column))?;
+        if let Some((last, _)) = self.cells.last() {
+            if column_index <= *last {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("the column {} is not after the last cell of the row", column),
+                ));
+            }
+        }
+        self.cells.push((column_index, cell));
+        Ok(())
     }
 
     pub fn cells(self) -> Vec<Cell<'a>> {
-        self.cells
+        self.cells.into_iter().map(|(_, cell)| cell).collect()
+    }
+
+    /// Sets the height of the row, in points, from 0 to 409.
+    pub fn height(mut self, height: f64) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Hides the row, like the detail rows of a collapsed group.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Sets the style of the whole row. It is also used by the cells of the row without their own style.
+    pub fn style(mut self, style: &'a CellStyle) -> Self {
+        self.style = Some(style);
+        self
+    }
+
+    /// Puts the row in an outline group, from 1 to 7. Rows with a level are grouped under the summary row next to them.
+    pub fn outline_level(mut self, outline_level: u8) -> Self {
+        self.outline_level = outline_level;
+        self
+    }
+
+    /// Marks the row as the summary row of a collapsed group. The rows of the group should also be hidden.
+    pub fn collapsed(mut self, collapsed: bool) -> Self {
+        self.collapsed = collapsed;
+        self
+    }
+
+    /// The first and the last column of the cells of the row.
+    pub(crate) fn column_range(&self) -> Option<(usize, usize)> {
+        let first = self.cells.first()?.0;
+        let last = self.cells.last()?.0;
+        Some((first, last))
+    }
+
+    pub(crate) fn validate(&self) -> IoResult<()> {
+        if self.outline_level > MAX_OUTLINE_LEVEL {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the outline level can't be greater than {}", MAX_OUTLINE_LEVEL),
+            ));
+        }
+        if let Some(height) = self.height {
+            if !(0.0..=MAX_ROW_HEIGHT).contains(&height) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid row height {}, it must be from 0 to {} points", height, MAX_ROW_HEIGHT),
+                ));
+            }
+        }
+        if self.cells.last().is_some_and(|(index, _)| *index >= MAX_COLUMNS) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("a row can't have more than {} columns", MAX_COLUMNS),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes the opening `<row>` tag with the row properties into a buffer, with the row number already formatted.
+    pub(crate) fn write_start(&self, row_number: &str, buffer: &mut Vec<u8>) -> IoResult<()> {
+        buffer.extend_from_slice(b"<row r=\"");
+        buffer.extend_from_slice(row_number.as_bytes());
+        buffer.push(b'"');
+        if let Some(style) = self.style {
+            buffer.extend_from_slice(b" s=\"");
+            buffer.extend_from_slice(itoa::Buffer::new().format(style.get_id()).as_bytes());
+            buffer.extend_from_slice(b"\" customFormat=\"1\"");
+        }
+        if let Some(height) = self.height {
+            write!(buffer, " ht=\"{}\" customHeight=\"1\"", height)?;
+        }
+        if self.hidden {
+            buffer.extend_from_slice(b" hidden=\"1\"");
+        }
+        if self.outline_level > 0 {
+            write!(buffer, " outlineLevel=\"{}\"", self.outline_level)?;
+        }
+        if self.collapsed {
+            buffer.extend_from_slice(b" collapsed=\"1\"");
+        }
+        buffer.extend_from_slice(b">\n");
+        Ok(())
+    }
+
+    /// The cells of the row with their column index, using the row style when they have no style.
+    pub(crate) fn into_styled_cells(self) -> impl Iterator<Item = (usize, Cell<'a>)> {
+        let style = self.style;
+        self.cells.into_iter().map(move |(index, mut cell)| {
+            if cell.style.is_none() && cell.hyperlink().is_none() {
+                cell.style = style;
+            }
+            (index, cell)
+        })
     }
 }
 
-/// A cell value. Right now, we can represent bool, f64 and strings
+/// A cell value. Right now, we can represent bool, f64, strings, hyperlinks and blank cells
 #[derive(Clone, Debug)]
 pub enum CellValue {
     Bool(bool),
     Number(f64),
     String(String),
+    Hyperlink(Hyperlink),
+    /// A cell without a value, only written to give it a style, like a border or a background.
+    Blank,
 }
 
 impl<'a> Cell<'a> {
+    /// A cell without a value but with a style.
+    pub fn blank(style: &'a CellStyle) -> Self {
+        Cell {
+            value: CellValue::Blank,
+            style: Some(style),
+        }
+    }
+
     pub fn write(
         &self,
-        column_index: u8,
+        column_index: usize,
         row_index: usize,
         writer: &mut impl Write,
     ) -> IoResult<()> {
-        let ref_id = ref_id(column_index, row_index);
+        let mut buffer = Vec::new();
+        self.write_to(
+            &column_letter(column_index),
+            itoa::Buffer::new().format(row_index),
+            &mut buffer,
+        );
+        writer.write_all(&buffer)
+    }
+
+    /// Writes the cell into a buffer, with its column letters and its row number already formatted, so nothing is allocated.
+    pub(crate) fn write_to(&self, column: &str, row_number: &str, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(b"<c r=\"");
+        buffer.extend_from_slice(column.as_bytes());
+        buffer.extend_from_slice(row_number.as_bytes());
+        buffer.push(b'"');
         match &self.value {
-            CellValue::Bool(b) => write!(
-                writer,
-                "<c r=\"{}\" t=\"b\"{}><v>{}</v></c>\n",
-                ref_id,
-                self.cell(),
-                if *b { 1 } else { 0 }
-            ),
+            CellValue::Bool(_) => buffer.extend_from_slice(b" t=\"b\""),
+            CellValue::String(_) | CellValue::Hyperlink(_) => buffer.extend_from_slice(b" t=\"str\""),
+            CellValue::Number(_) | CellValue::Blank => {}
+        }
+        let style_id = match (&self.value, self.style) {
+            (_, Some(style)) => Some(style.get_id()),
+            (CellValue::Hyperlink(_), None) => Some(HYPERLINK_STYLE_ID),
+            _ => None,
+        };
+        if let Some(style_id) = style_id {
+            buffer.extend_from_slice(b" s=\"");
+            buffer.extend_from_slice(itoa::Buffer::new().format(style_id).as_bytes());
+            buffer.push(b'"');
+        }
+        match &self.value {
+            CellValue::Bool(b) => buffer.extend_from_slice(if *b { b"><v>1</v></c>\n" } else { b"><v>0</v></c>\n" }),
             CellValue::Number(number) => {
-                write!(
-                    writer,
-                    "<c r=\"{}\"{}><v>{}</v></c>\n",
-                    ref_id,
-                    self.cell(),
-                    number
-                )
+                buffer.extend_from_slice(b"><v>");
+                write_number(*number, buffer);
+                buffer.extend_from_slice(b"</v></c>\n");
             }
             CellValue::String(string) => {
-                write!(
-                    writer,
-                    "<c r=\"{}\" t=\"str\"{}><v>{}</v></c>\n",
-                    ref_id,
-                    self.cell(),
-                    escape_xml(string.as_str())
-                )
+                buffer.extend_from_slice(b"><v>");
+                escape_xml_into(string, buffer);
+                buffer.extend_from_slice(b"</v></c>\n");
+            }
+            CellValue::Hyperlink(hyperlink) => {
+                buffer.extend_from_slice(b"><v>");
+                escape_xml_into(hyperlink.text(), buffer);
+                buffer.extend_from_slice(b"</v></c>\n");
             }
+            CellValue::Blank => buffer.extend_from_slice(b"/>\n"),
         }
     }
 
-    fn cell(&self) -> String {
-        match self.style {
-            None => "".to_string(),
-            Some(style) => format!(" s=\"{}\"", style.get_id()),
+    /// The hyperlink of this cell, if its value is one.
+    pub fn hyperlink(&self) -> Option<&Hyperlink> {
+        match &self.value {
+            CellValue::Hyperlink(hyperlink) => Some(hyperlink),
+            _ => None,
         }
     }
+
+}
+
+/// The integers Excel keeps exactly, written without a fraction.
+const MAX_EXACT_INTEGER: f64 = 1e15;
+
+/// Writes a number as its shortest representation: integers with itoa and the other numbers with ryu.
+fn write_number(number: f64, buffer: &mut Vec<u8>) {
+    if number.fract() == 0.0 && number.abs() < MAX_EXACT_INTEGER {
+        buffer.extend_from_slice(itoa::Buffer::new().format(number as i64).as_bytes());
+    } else if number.is_finite() {
+        buffer.extend_from_slice(ryu::Buffer::new().format_finite(number).as_bytes());
+    } else {
+        let _ = write!(buffer, "{}", number);
+    }
+}
+
+/// Like [escape_xml](escape_xml), but appending to a buffer. The text is copied as it is until the next character to escape.
+pub(crate) fn escape_xml_into(str: &str, buffer: &mut Vec<u8>) {
+    let bytes = str.as_bytes();
+    let mut start = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        let escaped: &[u8] = match byte {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'\'' => b"&apos;",
+            b'"' => b"&quot;",
+            _ => continue,
+        };
+        buffer.extend_from_slice(&bytes[start..i]);
+        buffer.extend_from_slice(escaped);
+        start = i + 1;
+    }
+    buffer.extend_from_slice(&bytes[start..]);
 }
 
 pub fn escape_xml(str: &str) -> String {
@@ -96,13 +314,91 @@ pub fn escape_xml(str: &str) -> String {
     result
 }
 
-fn ref_id(column_index: u8, row_index: usize) -> String {
+pub(crate) fn ref_id(column_index: usize, row_index: usize) -> String {
     format!("{}{}", column_letter(column_index), row_index)
 }
 
-fn column_letter(column_index: u8) -> String {
+/// Splits a cell reference like `B3` into its zero based column index and its row index.
+pub(crate) fn parse_ref_id(cell_ref: &str) -> Option<(usize, usize)> {
+    let letters = cell_ref
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .count();
+    if letters == 0 || letters > 3 {
+        return None;
+    }
+    let (column, row) = cell_ref.split_at(letters);
+    let column = column
+        .bytes()
+        .fold(0, |acc, c| acc * 26 + (c.to_ascii_uppercase() - b'A' + 1) as usize);
+    if !row.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let row: usize = row.parse().ok()?;
+    if column > MAX_COLUMNS || row == 0 || row > MAX_ROWS {
+        return None;
+    }
+    Some((column - 1, row))
+}
+
+/// Like [parse_ref_id](parse_ref_id), but failing with an [InvalidInput](ErrorKind::InvalidInput) error.
+pub(crate) fn parse_cell_ref(cell_ref: &str) -> IoResult<(usize, usize)> {
+    parse_ref_id(cell_ref).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid cell reference {}", cell_ref),
+        )
+    })
+}
+
+/// The reference of a range of cells, like `A1:D10`, or of a single cell, like `A1`.
+pub(crate) fn range_ref(first_row: usize, last_row: usize, first_column: usize, last_column: usize) -> String {
+    let first = ref_id(first_column, first_row);
+    let last = ref_id(last_column, last_row);
+    if first == last {
+        first
+    } else {
+        format!("{}:{}", first, last)
+    }
+}
+
+/// Splits a range of columns like `B:M`, or a single column like `N`, into its first and last zero based column indexes.
+pub(crate) fn parse_column_range(columns: &str) -> IoResult<(usize, usize)> {
+    let (first, last) = columns.split_once(':').unwrap_or((columns, columns));
+    let (first, _) = parse_cell_ref(&format!("{}1", first))?;
+    let (last, _) = parse_cell_ref(&format!("{}1", last))?;
+    Ok((first.min(last), first.max(last)))
+}
+
+/// The number of columns of a sheet supported by Excel.
+pub(crate) const MAX_COLUMNS: usize = 16_384;
+/// The number of rows of a sheet supported by Excel.
+pub(crate) const MAX_ROWS: usize = 1_048_576;
+
+/// Quotes a sheet name to be used in a formula, like `'Sheet 1'`.
+pub(crate) fn quote_sheet_name(sheet_name: &str) -> String {
+    format!("'{}'", sheet_name.replace('\'', "''"))
+}
+
+/// Builds an absolute reference to a cell or a range of a sheet, like `'Sheet 1'!$A$1:$B$2`. Returns None if the range is not valid.
+pub(crate) fn absolute_range(sheet_name: &str, range: &str) -> Option<String> {
+    let cells = range
+        .replace('$', "")
+        .split(':')
+        .map(|cell_ref| {
+            parse_ref_id(cell_ref)
+                .map(|(column, row)| format!("${}${}", column_letter(column), row))
+        })
+        .collect::<Option<Vec<String>>>()?;
+    if cells.len() > 2 {
+        return None;
+    }
+    Some(format!("{}!{}", quote_sheet_name(sheet_name), cells.join(":")))
+}
+
+pub(crate) fn column_letter(column_index: usize) -> String {
     let mut result = Vec::new();
-    let mut column_index = column_index as i16;
+    let mut column_index = column_index as i64;
     while column_index >= 0 {
         result.push(number_to_letter((column_index % 26) as u8));
         column_index = column_index / 26 - 1;
@@ -163,6 +459,12 @@ impl From<String> for CellValue {
     }
 }
 
+impl From<Hyperlink> for CellValue {
+    fn from(data: Hyperlink) -> Self {
+        Self::Hyperlink(data)
+    }
+}
+
 impl From<&str> for CellValue {
     fn from(data: &str) -> Self {
         Self::String(data.to_string())
@@ -1,37 +1,184 @@
-use std::io::{Result as IoResult, Seek, Write};
-use zip::{write::FileOptions, ZipWriter};
-use crate::excel::SheetWriter;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result as IoResult, Write};
+use std::rc::Rc;
+use crate::excel::column::Columns;
+use crate::excel::comment::Comments;
+use crate::excel::drawing::Drawing;
+use crate::excel::relationships::{Relationships, DRAWING};
+use crate::excel::row::{absolute_range, parse_cell_ref, MAX_OUTLINE_LEVEL};
+use crate::excel::{Chart, Column, HeaderFooter, PackageWriter, SheetProtection, SheetWriter};
+
+/// A sheet written on a worker thread by [write_sheets_in_parallel](crate::excel::WorkBook::write_sheets_in_parallel), compressed into a temporary file.
+pub type ParallelSheet<'a> = Sheet<'a, BufWriter<File>>;
 
 /// A XLSX sheet.
 pub struct Sheet<'a, W>
 where
-    W: Write + Seek,
+    W: Write,
 {
     id: usize,
-    zip_writer: &'a mut ZipWriter<W>,
+    zip_writer: &'a mut PackageWriter<W>,
+    parts: Rc<RefCell<SheetParts>>,
 }
 
-/// Responsible to write a sheet into the workbook.
+/// What a sheet collects while it is written and that the workbook needs to write its own parts, like the sheet relationships.
+#[derive(Debug)]
+pub(crate) struct SheetParts {
+    pub(crate) id: usize,
+    pub(crate) relationships: Relationships,
+    pub(crate) comments: Comments,
+    pub(crate) drawing: Drawing,
+    /// Built-in names the sheet needs in the workbook, like its print area, as `(name, formula)`.
+    pub(crate) defined_names: Vec<(&'static str, String)>,
+    pub(crate) header_footer: Option<HeaderFooter>,
+    pub(crate) protection: Option<SheetProtection>,
+    pub(crate) format: SheetFormat,
+}
+
+/// Settings written in the sheet header, so they need to be set before the sheet is written.
+#[derive(Clone, Debug)]
+pub(crate) struct SheetFormat {
+    pub(crate) default_row_height: Option<f64>,
+    pub(crate) row_outline_levels: u8,
+    pub(crate) summary_rows_below: bool,
+    pub(crate) summary_columns_right: bool,
+    pub(crate) columns: Columns,
+    pub(crate) dimension: Option<String>,
+}
 
+impl SheetParts {
+    pub(crate) fn new(id: usize) -> Self {
+        Self {
+            id,
+            relationships: Relationships::default(),
+            comments: Comments::default(),
+            drawing: Drawing::default(),
+            defined_names: Vec::new(),
+            header_footer: None,
+            protection: None,
+            format: SheetFormat {
+                default_row_height: None,
+                row_outline_levels: 0,
+                summary_rows_below: true,
+                summary_columns_right: true,
+                columns: Columns::default(),
+                dimension: None,
+            },
+        }
+    }
+
+    /// The name of the sheet shown in the workbook and used in formulas.
+    pub(crate) fn name(&self) -> String {
+        format!("Sheet {}", self.id)
+    }
+
+    /// The drawing of the sheet, added to the sheet relationships the first time it is needed.
+    pub(crate) fn drawing_mut(&mut self) -> &mut Drawing {
+        if self.drawing.relationship_id.is_none() {
+            let id = self.relationships.add(
+                DRAWING,
+                format!("../drawings/drawing{}.xml", self.id),
+                false,
+            );
+            self.drawing.relationship_id = Some(id);
+        }
+        &mut self.drawing
+    }
+
+    pub(crate) fn insert_chart(&mut self, cell_ref: &str, chart: Chart) -> IoResult<()> {
+        let (column_index, row_index) = parse_cell_ref(cell_ref)?;
+        chart.validate()?;
+        let sheet_id = self.id;
+        self.drawing_mut()
+            .add_chart(sheet_id, column_index, row_index, chart);
+        Ok(())
+    }
+}
+
+/// Responsible to write a sheet into the workbook.
 impl<'a, W> Sheet<'a, W>
 where
-    W: Write + Seek,
+    W: Write,
 {
-    pub(crate) fn new(id: usize, zip_writer: &'a mut ZipWriter<W>) -> Self {
-        Self { id, zip_writer }
+    pub(crate) fn new(id: usize, zip_writer: &'a mut PackageWriter<W>, parts: Rc<RefCell<SheetParts>>) -> Self {
+        Self { id, zip_writer, parts }
+    }
+
+    /// Inserts a chart with its top left corner on a cell, like `E2`. The chart can reference ranges of any sheet of the workbook, including the ones not written yet.
+    pub fn insert_chart(&mut self, cell_ref: &str, chart: Chart) -> IoResult<()> {
+        self.parts.borrow_mut().insert_chart(cell_ref, chart)
+    }
+
+    /// Sets the headers and footers printed on every page of the sheet.
+    pub fn set_header_footer(&mut self, header_footer: HeaderFooter) -> IoResult<()> {
+        header_footer.validate()?;
+        self.parts.borrow_mut().header_footer = Some(header_footer);
+        Ok(())
+    }
+
+    /// Protects the sheet, so only the cells with an unlocked style can be edited.
+    pub fn set_protection(&mut self, protection: SheetProtection) {
+        self.parts.borrow_mut().protection = Some(protection);
+    }
+
+    /// Sets the height, in points, of the rows without a custom height.
+    pub fn set_default_row_height(&mut self, height: f64) {
+        self.parts.borrow_mut().format.default_row_height = Some(height);
+    }
+
+    /// Sets how many row outline levels the sheet uses, so Excel shows the buttons to expand and collapse them.
+    /// As the sheet is streamed, it needs to be known before writing it.
+    pub fn set_row_outline_levels(&mut self, levels: u8) -> IoResult<()> {
+        if levels > MAX_OUTLINE_LEVEL {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the outline level can't be greater than {}", MAX_OUTLINE_LEVEL),
+            ));
+        }
+        self.parts.borrow_mut().format.row_outline_levels = levels;
+        Ok(())
+    }
+
+    /// Sets whether the summary rows are below their detail rows, as by default, or above them.
+    pub fn set_summary_rows_below(&mut self, below: bool) {
+        self.parts.borrow_mut().format.summary_rows_below = below;
+    }
+
+    /// Sets the properties of a range of columns, like `B:D`, or of a single column, like `E`.
+    pub fn set_columns(&mut self, columns: &str, column: Column) -> IoResult<()> {
+        self.parts.borrow_mut().format.columns.set(columns, column)
+    }
+
+    /// Sets whether the summary columns are at the right of their detail columns, as by default, or at the left.
+    pub fn set_summary_columns_right(&mut self, right: bool) {
+        self.parts.borrow_mut().format.summary_columns_right = right;
+    }
+
+    /// Declares the range of the cells the sheet will have, like `A1:H1000`, written as its `<dimension>`.
+    /// Some readers use it to size the grid before loading the cells. When the sheet is deflated, it is streamed into the workbook already compressed and can't be changed, so it needs to be declared before writing it, or the sheet has no `<dimension>`.
+    /// The range of the cells written replaces it when the sheet is stored into a seekable output. Writing a row with cells outside of it fails.
+    pub fn set_dimension(&mut self, range: &str) -> IoResult<()> {
+        if absolute_range("", range).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid dimension {}", range),
+            ));
+        }
+        self.parts.borrow_mut().format.dimension = Some(range.replace('$', "").to_ascii_uppercase());
+        Ok(())
     }
 
     /// Receives a closure that will write the sheet. The closure receive a [SheetWriter](SheetWriter) that can be used to write the rows into the sheet.
     /// You don't need to call [finish](SheetWriter::finish) as it will be called for you.
     pub fn write_sheet<T>(
         self,
-        is_large: bool,
-        function: impl FnOnce(&mut SheetWriter<&mut ZipWriter<W>>) -> IoResult<T>,
+        function: impl FnOnce(&mut SheetWriter<&mut PackageWriter<W>>) -> IoResult<T>,
     ) -> IoResult<T> {
-        let options = FileOptions::default().large_file(is_large);
         self.zip_writer
-            .start_file(format!("xl/worksheets/sheet{}.xml", self.id), options)?;
-        let mut sheet_writer = SheetWriter::start(&mut *self.zip_writer)?;
+            .start_file(format!("xl/worksheets/sheet{}.xml", self.id))?;
+        let mut sheet_writer = SheetWriter::start_with_parts(&mut *self.zip_writer, self.parts)?
+            .patch_dimension_with(reserve_in_part, fill_in_part);
         let result = function(&mut sheet_writer)?;
         sheet_writer.finish()?;
         Ok(result)
@@ -39,11 +186,18 @@ where
 
     /// Antoher way to write a sheet. Insted of using a closure that has access to the [SheetWriter](SheetWriter). This returns the [SheetWriter](SheetWriter) directly and you can use it to write the sheet.
     /// You need to call [finish](SheetWriter::finish).
-    /// set is_large to true when the file is approximately bigger than 4GiB
-    pub fn sheet_writer(self, is_large: bool) -> IoResult<SheetWriter<&'a mut ZipWriter<W>>> {
-        let options = FileOptions::default().large_file(is_large);
+    pub fn sheet_writer(self) -> IoResult<SheetWriter<&'a mut PackageWriter<W>>> {
         self.zip_writer
-            .start_file(format!("xl/worksheets/sheet{}.xml", self.id), options)?;
-        Ok(SheetWriter::start(&mut *self.zip_writer)?)
+            .start_file(format!("xl/worksheets/sheet{}.xml", self.id))?;
+        Ok(SheetWriter::start_with_parts(&mut *self.zip_writer, self.parts)?
+            .patch_dimension_with(reserve_in_part, fill_in_part))
     }
 }
+
+fn reserve_in_part<W: Write>(package: &mut &mut PackageWriter<W>, placeholder: &[u8]) -> IoResult<Option<u64>> {
+    package.reserve(placeholder)
+}
+
+fn fill_in_part<W: Write>(package: &mut &mut PackageWriter<W>, offset: u64, bytes: &[u8]) -> IoResult<()> {
+    package.fill_reserved(offset, bytes)
+}
@@ -1,5 +1,15 @@
-use crate::excel::Row;
-use std::io::{Result as IoResult, Write};
+use crate::excel::relationships::{COMMENTS, HYPERLINK, VML_DRAWING};
+use crate::excel::row::{
+    column_letter, escape_xml, parse_cell_ref, range_ref, ref_id, MAX_COLUMNS, MAX_ROWS,
+};
+use crate::excel::sheet::SheetParts;
+use crate::excel::{
+    Chart, HyperlinkTarget, Image, ImageOptions, PageSetup, Row, SparklineGroup,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result as IoResult, Seek, SeekFrom, Write};
+use std::rc::Rc;
 
 pub struct SheetWriter<W>
 where
@@ -7,23 +17,227 @@ where
 {
     writer: W,
     row_index: usize,
+    written_header: bool,
     written_footer: bool,
+    hyperlinks: Vec<HyperlinkRef>,
+    url_relationships: HashMap<String, usize>,
+    /// The sparkline groups, with the rows written since they were added, as runs of consecutive rows `(first, last)`.
+    sparkline_groups: Vec<(SparklineGroup, Vec<(usize, usize)>)>,
+    page_setup: Option<PageSetup>,
+    row_breaks: Vec<usize>,
+    column_breaks: Vec<usize>,
+    /// The cells written so far, as `(first row, last row, first column, last column)`.
+    used_range: Option<(usize, usize, usize, usize)>,
+    /// Reserves the `<dimension>` in the header and fills it at the end, only when the inner writer supports it.
+    patch_dimension: Option<(ReserveFn<W>, FillFn<W>)>,
+    dimension_position: Option<u64>,
+    /// The range declared with [Sheet::set_dimension](crate::excel::Sheet::set_dimension), as `(first row, last row, first column, last column)`, read when the header is written.
+    declared_range: Option<(usize, usize, usize, usize)>,
+    parts: Rc<RefCell<SheetParts>>,
+    /// The XML of the row being written, reused for every row so it is written at once.
+    buffer: Vec<u8>,
+    /// The letters of the columns written so far, like `AB`, computed once.
+    column_letters: Vec<String>,
+}
+
+/// The longest `<dimension>`, reserved in the header when it is patched at the end.
+const DIMENSION_PLACEHOLDER_LENGTH: usize = "<dimension ref=\"A1:XFD1048576\"/>".len();
+
+/// Writes a placeholder into the writer, giving back its position, or `None` when it can't be patched later.
+pub(crate) type ReserveFn<W> = fn(&mut W, &[u8]) -> IoResult<Option<u64>>;
+/// Replaces the placeholder at a position with bytes of the same length.
+pub(crate) type FillFn<W> = fn(&mut W, u64, &[u8]) -> IoResult<()>;
+
+/// A hyperlink already written as a cell, waiting to be listed in the sheet footer.
+struct HyperlinkRef {
+    cell_ref: String,
+    relationship_id: Option<usize>,
+    location: Option<String>,
+    display: String,
+    tooltip: Option<String>,
 }
 
 impl<W> SheetWriter<W>
 where
     W: Write,
 {
-    /// Writes a row into the sheet.
+    /// Writes a row into the sheet, after the last row written.
     pub fn write_row(&mut self, row: Row) -> IoResult<()> {
-        self.row_index += 1;
-        write!(self.writer, "<row r=\"{}\">\n", self.row_index)?;
-        for (i, c) in row.cells().into_iter().enumerate() {
-            c.write(i as u8, self.row_index, &mut self.writer)?;
+        self.write_row_at(self.row_index + 1, row)
+    }
+
+    /// Writes a row at a row number, starting at 1, leaving empty the rows between the last row written and this one.
+    /// As the sheet is streamed, the rows can only be written in ascending order.
+    pub fn write_row_at(&mut self, row_index: usize, row: Row) -> IoResult<()> {
+        if row_index <= self.row_index || row_index > MAX_ROWS {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the row {} must be after the last row written, {}, and up to {}",
+                    row_index, self.row_index, MAX_ROWS
+                ),
+            ));
         }
-        write!(self.writer, "\n</row>\n")?;
-        self.writer.flush()
-        // Ok(())
+        row.validate()?;
+        self.write_header()?;
+        let column_range = row.column_range();
+        if let (Some((first_row, last_row, first, last)), Some((first_column, last_column))) =
+            (self.declared_range, column_range)
+        {
+            if row_index < first_row || row_index > last_row || first_column < first || last_column > last {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the cells {} are outside of the dimension {} declared for the sheet",
+                        range_ref(row_index, row_index, first_column, last_column),
+                        range_ref(first_row, last_row, first, last)
+                    ),
+                ));
+            }
+        }
+        self.row_index = row_index;
+        if let Some((first_column, last_column)) = column_range {
+            self.used_range = Some(match self.used_range {
+                None => (row_index, row_index, first_column, last_column),
+                Some((first_row, _, first, last)) => (
+                    first_row,
+                    row_index,
+                    first.min(first_column),
+                    last.max(last_column),
+                ),
+            });
+            while self.column_letters.len() <= last_column {
+                self.column_letters.push(column_letter(self.column_letters.len()));
+            }
+        }
+        let mut row_number = itoa::Buffer::new();
+        let row_number = row_number.format(row_index);
+        self.buffer.clear();
+        row.write_start(row_number, &mut self.buffer)?;
+        for (i, c) in row.into_styled_cells() {
+            if let Some(hyperlink) = c.hyperlink() {
+                let cell_ref = ref_id(i, self.row_index);
+                let (relationship_id, location) = match hyperlink.target() {
+                    HyperlinkTarget::Url(url) => (Some(self.url_relationship(url)), None),
+                    HyperlinkTarget::Location(location) => (None, Some(location.clone())),
+                };
+                self.hyperlinks.push(HyperlinkRef {
+                    cell_ref,
+                    relationship_id,
+                    location,
+                    display: hyperlink.text().to_string(),
+                    tooltip: hyperlink.get_tooltip().map(str::to_string),
+                });
+            }
+            c.write_to(&self.column_letters[i], row_number, &mut self.buffer);
+        }
+        self.buffer.extend_from_slice(b"\n</row>\n");
+        for (_, rows) in self.sparkline_groups.iter_mut() {
+            match rows.last_mut() {
+                Some((_, last)) if *last + 1 == row_index => *last = row_index,
+                _ => rows.push((row_index, row_index)),
+            }
+        }
+        self.writer.write_all(&self.buffer)
+    }
+
+    /// Attaches a note to a cell, like `B3`. It can be called at any moment while the sheet is written, even for rows already written.
+    pub fn add_comment(&mut self, cell_ref: &str, author: &str, text: &str) -> IoResult<()> {
+        let (column_index, row_index) = parse_cell_ref(cell_ref)?;
+        let mut parts = self.parts.borrow_mut();
+        let first = parts.comments.is_empty();
+        parts.comments.add(
+            cell_ref.to_ascii_uppercase(),
+            column_index,
+            row_index,
+            author,
+            text,
+        )?;
+        if first {
+            let id = parts.id;
+            parts
+                .relationships
+                .add(COMMENTS, format!("../comments{}.xml", id), false);
+            let vml_id = parts.relationships.add(
+                VML_DRAWING,
+                format!("../drawings/vmlDrawing{}.vml", id),
+                false,
+            );
+            parts.comments.vml_relationship_id = Some(vml_id);
+        }
+        Ok(())
+    }
+
+    /// Inserts an image with its top left corner on a cell, like `A1`. It can be called at any moment while the sheet is written.
+    pub fn insert_image(
+        &mut self,
+        cell_ref: &str,
+        image: &Image,
+        options: ImageOptions,
+    ) -> IoResult<()> {
+        let (column_index, row_index) = parse_cell_ref(cell_ref)?;
+        options.validate()?;
+        let mut parts = self.parts.borrow_mut();
+        let sheet_id = parts.id;
+        parts
+            .drawing_mut()
+            .add_image(sheet_id, column_index, row_index, image, &options);
+        Ok(())
+    }
+
+    /// Inserts a chart with its top left corner on a cell, like `E2`. The chart can reference ranges of any sheet of the workbook.
+    pub fn insert_chart(&mut self, cell_ref: &str, chart: Chart) -> IoResult<()> {
+        self.parts.borrow_mut().insert_chart(cell_ref, chart)
+    }
+
+    /// Adds a sparkline to every row written from now on, until the sheet is finished. Call it after the header rows so they don't get one.
+    /// The rows skipped by [write_row_at](SheetWriter::write_row_at) don't get one.
+    pub fn add_sparklines(&mut self, group: SparklineGroup) -> IoResult<()> {
+        group.columns()?;
+        self.sparkline_groups.push((group, Vec::new()));
+        Ok(())
+    }
+
+    /// Sets how the sheet is printed. Fitting to pages needs to be set before writing the first row.
+    pub fn set_page_setup(&mut self, page_setup: PageSetup) -> IoResult<()> {
+        page_setup.validate()?;
+        if page_setup.fits_to_page() && self.written_header {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "fitting to pages must be set before writing the first row",
+            ));
+        }
+        let mut parts = self.parts.borrow_mut();
+        let sheet_name = parts.name();
+        parts.defined_names = page_setup.defined_names(&sheet_name);
+        self.page_setup = Some(page_setup);
+        Ok(())
+    }
+
+    /// Starts a new printed page after the last written row. Nothing happens if no row was written yet.
+    pub fn insert_page_break(&mut self) -> IoResult<()> {
+        if self.row_index == 0 || self.row_breaks.last() == Some(&self.row_index) {
+            return Ok(());
+        }
+        check_page_breaks(&self.row_breaks)?;
+        self.row_breaks.push(self.row_index);
+        Ok(())
+    }
+
+    /// Starts a new printed page before the column with the given zero based index, so `3` breaks between `C` and `D`.
+    pub fn insert_column_break(&mut self, column_index: usize) -> IoResult<()> {
+        if column_index == 0 || column_index >= MAX_COLUMNS {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid column break {}", column_index),
+            ));
+        }
+        if !self.column_breaks.contains(&column_index) {
+            check_page_breaks(&self.column_breaks)?;
+            self.column_breaks.push(column_index);
+            self.column_breaks.sort_unstable();
+        }
+        Ok(())
     }
 
     /// Finish the sheet. Necessary to be called if you got the [SheetWriter](SheetWriter) from [Sheet::sheet_writer](Sheet::sheet_writer). We also try to execute this in the [Drop](SheetWriter::drop), but it is a good practice to always finish the sheet.
@@ -35,32 +249,276 @@ where
     where
         W: Write,
     {
-        let mut writer = Self {
+        Self::start_with_parts(writer, Rc::new(RefCell::new(SheetParts::new(1))))
+    }
+
+    /// Like [start](SheetWriter::start), but as the writer can seek, the `<dimension>` of the sheet is reserved in its header and written at the end with the range of the cells written.
+    pub fn start_seekable(writer: W) -> IoResult<Self>
+    where
+        W: Write + Seek,
+    {
+        let sheet_writer = Self::start(writer)?;
+        Ok(sheet_writer.patch_dimension_with(
+            |writer, placeholder| {
+                writer.flush()?;
+                let position = writer.stream_position()?;
+                writer.write_all(placeholder)?;
+                Ok(Some(position))
+            },
+            |writer, position, bytes| {
+                writer.flush()?;
+                let end = writer.stream_position()?;
+                writer.seek(SeekFrom::Start(position))?;
+                writer.write_all(bytes)?;
+                writer.flush()?;
+                writer.seek(SeekFrom::Start(end))?;
+                Ok(())
+            },
+        ))
+    }
+
+    /// Writes the `<dimension>` of the cells written at the end, reserving it in the header with these functions.
+    pub(crate) fn patch_dimension_with(mut self, reserve: ReserveFn<W>, fill: FillFn<W>) -> Self {
+        self.patch_dimension = Some((reserve, fill));
+        self
+    }
+
+    /// The range of the cells written so far, like `A1:D10`. It is `A1` while the sheet is empty.
+    pub fn dimension(&self) -> String {
+        match self.used_range {
+            None => "A1".to_string(),
+            Some((first_row, last_row, first_column, last_column)) => {
+                range_ref(first_row, last_row, first_column, last_column)
+            }
+        }
+    }
+
+    pub(crate) fn start_with_parts(writer: W, parts: Rc<RefCell<SheetParts>>) -> IoResult<Self> {
+        Ok(Self {
             writer,
             row_index: 0,
+            written_header: false,
             written_footer: false,
-        };
-        writer.write_header()?;
-        Ok(writer)
-        
+            hyperlinks: Vec::new(),
+            url_relationships: HashMap::new(),
+            sparkline_groups: Vec::new(),
+            page_setup: None,
+            row_breaks: Vec::new(),
+            column_breaks: Vec::new(),
+            used_range: None,
+            patch_dimension: None,
+            dimension_position: None,
+            declared_range: None,
+            parts,
+            buffer: Vec::new(),
+            column_letters: Vec::new(),
+        })
+    }
+
+    /// Returns the relationship id of an external url, reusing it when the same url was already linked in this sheet.
+    fn url_relationship(&mut self, url: &str) -> usize {
+        if let Some(id) = self.url_relationships.get(url) {
+            return *id;
+        }
+        let id = self
+            .parts
+            .borrow_mut()
+            .relationships
+            .add(HYPERLINK, url.to_string(), true);
+        self.url_relationships.insert(url.to_string(), id);
+        id
     }
 
+    /// Writes the header the first time it is called. It is delayed until the first row so the settings done before it can still change it.
     fn write_header(&mut self) -> IoResult<()> {
+        if self.written_header {
+            return Ok(());
+        }
+        self.written_header = true;
         write!(
             self.writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
 <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#
         )?;
+        let fits_to_page = self.page_setup.as_ref().is_some_and(PageSetup::fits_to_page);
+        let format = self.parts.borrow().format.clone();
+        let summary_moved = !format.summary_rows_below || !format.summary_columns_right;
+        if fits_to_page || summary_moved {
+            write!(self.writer, "\n<sheetPr>")?;
+            if summary_moved {
+                write!(self.writer, "<outlinePr")?;
+                if !format.summary_rows_below {
+                    write!(self.writer, " summaryBelow=\"0\"")?;
+                }
+                if !format.summary_columns_right {
+                    write!(self.writer, " summaryRight=\"0\"")?;
+                }
+                write!(self.writer, "/>")?;
+            }
+            if fits_to_page {
+                write!(self.writer, "<pageSetUpPr fitToPage=\"1\"/>")?;
+            }
+            write!(self.writer, "</sheetPr>")?;
+        }
+        if let Some((reserve, _)) = self.patch_dimension {
+            let placeholder = format!("\n{}", " ".repeat(DIMENSION_PLACEHOLDER_LENGTH));
+            self.dimension_position = reserve(&mut self.writer, placeholder.as_bytes())?;
+        }
+        if let Some(dimension) = &format.dimension {
+            let (first, last) = dimension.split_once(':').unwrap_or((dimension, dimension));
+            let (first_column, first_row) = parse_cell_ref(first)?;
+            let (last_column, last_row) = parse_cell_ref(last)?;
+            self.declared_range = Some((first_row, last_row, first_column, last_column));
+            if self.dimension_position.is_none() {
+                write!(self.writer, "\n<dimension ref=\"{}\"/>", dimension)?;
+            }
+        }
+        let column_outline_levels = format.columns.outline_levels();
+        if format.default_row_height.is_some()
+            || format.row_outline_levels > 0
+            || column_outline_levels > 0
+        {
+            write!(
+                self.writer,
+                "\n<sheetFormatPr defaultRowHeight=\"{}\"",
+                format.default_row_height.unwrap_or(DEFAULT_ROW_HEIGHT)
+            )?;
+            if format.default_row_height.is_some() {
+                write!(self.writer, " customHeight=\"1\"")?;
+            }
+            if format.row_outline_levels > 0 {
+                write!(self.writer, " outlineLevelRow=\"{}\"", format.row_outline_levels)?;
+            }
+            if column_outline_levels > 0 {
+                write!(self.writer, " outlineLevelCol=\"{}\"", column_outline_levels)?;
+            }
+            write!(self.writer, "/>")?;
+        }
+        if !format.columns.is_empty() {
+            writeln!(self.writer)?;
+            format.columns.write(&mut self.writer)?;
+        }
         write!(self.writer, "\n<sheetData>\n")?;
         self.writer.flush()
         // Ok(())
     }
 
     fn write_footer(&mut self) -> IoResult<()> {
+        self.write_header()?;
         self.written_footer = true;
-        write!(self.writer, "\n</sheetData>\n</worksheet>\n").expect("unable write sheet footer");
+        write!(self.writer, "\n</sheetData>\n")?;
+        if let Some(protection) = &self.parts.borrow().protection {
+            protection.write(&mut self.writer)?;
+        }
+        self.write_hyperlinks()?;
+        if let Some(page_setup) = &self.page_setup {
+            page_setup.write(&mut self.writer)?;
+        }
+        if let Some(header_footer) = &self.parts.borrow().header_footer {
+            header_footer.write(&mut self.writer)?;
+        }
+        write_breaks(&mut self.writer, "rowBreaks", &self.row_breaks, MAX_COLUMNS - 1)?;
+        write_breaks(&mut self.writer, "colBreaks", &self.column_breaks, MAX_ROWS - 1)?;
+        if let Some(id) = self.parts.borrow().drawing.relationship_id {
+            writeln!(self.writer, "<drawing r:id=\"rId{}\"/>", id)?;
+        }
+        if let Some(id) = self.parts.borrow().comments.vml_relationship_id {
+            writeln!(self.writer, "<legacyDrawing r:id=\"rId{}\"/>", id)?;
+        }
+        self.write_sparklines()?;
+        writeln!(self.writer, "</worksheet>").expect("unable write sheet footer");
+        self.write_dimension()?;
         self.writer.flush()
     }
+
+    /// Replaces the placeholder reserved in the header with the `<dimension>` of the cells written.
+    fn write_dimension(&mut self) -> IoResult<()> {
+        let (fill, position) = match (self.patch_dimension, self.dimension_position) {
+            (Some((_, fill)), Some(position)) => (fill, position),
+            _ => return Ok(()),
+        };
+        let mut dimension = format!("\n<dimension ref=\"{}\"", self.dimension());
+        dimension.push_str(&" ".repeat(DIMENSION_PLACEHOLDER_LENGTH + 1 - dimension.len() - 2));
+        dimension.push_str("/>");
+        fill(&mut self.writer, position, dimension.as_bytes())
+    }
+
+    fn write_sparklines(&mut self) -> IoResult<()> {
+        let groups = self
+            .sparkline_groups
+            .iter()
+            .filter(|(_, rows)| !rows.is_empty())
+            .collect::<Vec<_>>();
+        if groups.is_empty() {
+            return Ok(());
+        }
+        write!(
+            self.writer,
+            r#"<extLst>
+<ext uri="{{05C60535-1F16-4fd2-B633-F4F36F0B64E0}}" xmlns:x14="http://schemas.microsoft.com/office/spreadsheetml/2009/9/main">
+<x14:sparklineGroups xmlns:xm="http://schemas.microsoft.com/office/excel/2006/main">
+"#
+        )?;
+        let sheet_name = self.parts.borrow().name();
+        for (group, rows) in groups {
+            group.write(&mut self.writer, &sheet_name, rows)?;
+        }
+        writeln!(self.writer, "</x14:sparklineGroups>\n</ext>\n</extLst>")
+    }
+
+    fn write_hyperlinks(&mut self) -> IoResult<()> {
+        if self.hyperlinks.is_empty() {
+            return Ok(());
+        }
+        writeln!(self.writer, "<hyperlinks>")?;
+        for hyperlink in self.hyperlinks.iter() {
+            write!(self.writer, "<hyperlink ref=\"{}\"", hyperlink.cell_ref)?;
+            if let Some(id) = hyperlink.relationship_id {
+                write!(self.writer, " r:id=\"rId{}\"", id)?;
+            }
+            if let Some(location) = &hyperlink.location {
+                write!(self.writer, " location=\"{}\"", escape_xml(location))?;
+            }
+            if let Some(tooltip) = &hyperlink.tooltip {
+                write!(self.writer, " tooltip=\"{}\"", escape_xml(tooltip))?;
+            }
+            writeln!(self.writer, " display=\"{}\"/>", escape_xml(&hyperlink.display))?;
+        }
+        writeln!(self.writer, "</hyperlinks>")
+    }
+}
+
+/// The row height, in points, Excel uses for the default font.
+const DEFAULT_ROW_HEIGHT: f64 = 15.0;
+
+/// The most manual page breaks Excel supports in each direction.
+const MAX_PAGE_BREAKS: usize = 1023;
+
+fn check_page_breaks(breaks: &[usize]) -> IoResult<()> {
+    if breaks.len() >= MAX_PAGE_BREAKS {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("a sheet can't have more than {} page breaks", MAX_PAGE_BREAKS),
+        ));
+    }
+    Ok(())
+}
+
+fn write_breaks(writer: &mut impl Write, name: &str, breaks: &[usize], max: usize) -> IoResult<()> {
+    if breaks.is_empty() {
+        return Ok(());
+    }
+    write!(
+        writer,
+        "<{} count=\"{}\" manualBreakCount=\"{}\">",
+        name,
+        breaks.len(),
+        breaks.len()
+    )?;
+    for id in breaks {
+        write!(writer, "<brk id=\"{}\" max=\"{}\" man=\"1\"/>", id, max)?;
+    }
+    writeln!(writer, "</{}>", name)
 }
 
 impl<W> Drop for SheetWriter<W>
@@ -69,8 +527,8 @@ where
 {
     /// Drops the [SheetWriter](SheetWriter) and tries to finish it if not already finished. This might panic if we fail to write the footer of the sheet.
     fn drop(&mut self) {
-        if self.written_footer == false {
+        if !self.written_footer {
             self.write_footer().expect("Error written sheet footer");
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+use crate::excel::row::{
+    column_letter, escape_xml, parse_cell_ref, parse_column_range, quote_sheet_name,
+};
+use std::io::{Result as IoResult, Write};
+
+/// The kind of sparkline to draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SparklineType {
+    Line,
+    Column,
+    WinLoss,
+}
+
+/// A group of sparklines sharing the same look. Once added with [SheetWriter::add_sparklines](crate::excel::SheetWriter::add_sparklines), every following row gets a sparkline in the location column drawn from its own values in the data columns.
+#[derive(Clone, Debug)]
+pub struct SparklineGroup {
+    sparkline_type: SparklineType,
+    data_columns: String,
+    location_column: String,
+    series_color: (u8, u8, u8),
+    negative_color: (u8, u8, u8),
+    marker_color: (u8, u8, u8),
+    markers: bool,
+    high_point: bool,
+    low_point: bool,
+    first_point: bool,
+    last_point: bool,
+    negative_points: bool,
+}
+
+impl SparklineGroup {
+    /// Creates a group drawing the values of the `data_columns`, like `B:M`, into the `location_column`, like `N`.
+    pub fn new(
+        sparkline_type: SparklineType,
+        data_columns: impl Into<String>,
+        location_column: impl Into<String>,
+    ) -> Self {
+        Self {
+            sparkline_type,
+            data_columns: data_columns.into(),
+            location_column: location_column.into(),
+            series_color: (0x37, 0x60, 0x92),
+            negative_color: (0xD0, 0x00, 0x00),
+            marker_color: (0xD0, 0x00, 0x00),
+            markers: false,
+            high_point: false,
+            low_point: false,
+            first_point: false,
+            last_point: false,
+            negative_points: false,
+        }
+    }
+
+    pub fn series_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.series_color = rgb;
+        self
+    }
+
+    /// The color of the negative points, also used for the losses of a win/loss sparkline.
+    pub fn negative_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.negative_color = rgb;
+        self
+    }
+
+    /// The color of the markers and of the highlighted points.
+    pub fn marker_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.marker_color = rgb;
+        self
+    }
+
+    /// Shows a marker on every point of a line sparkline.
+    pub fn markers(mut self, markers: bool) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    pub fn high_point(mut self, high_point: bool) -> Self {
+        self.high_point = high_point;
+        self
+    }
+
+    pub fn low_point(mut self, low_point: bool) -> Self {
+        self.low_point = low_point;
+        self
+    }
+
+    pub fn first_point(mut self, first_point: bool) -> Self {
+        self.first_point = first_point;
+        self
+    }
+
+    pub fn last_point(mut self, last_point: bool) -> Self {
+        self.last_point = last_point;
+        self
+    }
+
+    pub fn negative_points(mut self, negative_points: bool) -> Self {
+        self.negative_points = negative_points;
+        self
+    }
+
+    /// Resolves the columns of the group into `(first data column, last data column, location column)`.
+    pub(crate) fn columns(&self) -> IoResult<(usize, usize, usize)> {
+        let (first, last) = parse_column_range(&self.data_columns)?;
+        let (location, _) = parse_cell_ref(&format!("{}1", self.location_column))?;
+        Ok((first, last, location))
+    }
+
+    /// Writes the group with one sparkline for each row written, given as runs of consecutive rows `(first, last)`.
+    pub(crate) fn write(
+        &self,
+        writer: &mut impl Write,
+        sheet_name: &str,
+        rows: &[(usize, usize)],
+    ) -> IoResult<()> {
+        let (first_column, last_column, location_column) = self.columns()?;
+        write!(writer, "<x14:sparklineGroup")?;
+        match self.sparkline_type {
+            SparklineType::Line => {}
+            SparklineType::Column => write!(writer, " type=\"column\"")?,
+            SparklineType::WinLoss => write!(writer, " type=\"stacked\"")?,
+        }
+        write!(writer, " displayEmptyCellsAs=\"gap\"")?;
+        let flags = [
+            ("markers", self.markers),
+            ("high", self.high_point),
+            ("low", self.low_point),
+            ("first", self.first_point),
+            ("last", self.last_point),
+            ("negative", self.negative_points),
+        ];
+        for (name, enabled) in flags.iter() {
+            if *enabled {
+                write!(writer, " {}=\"1\"", name)?;
+            }
+        }
+        writeln!(writer, ">")?;
+        let colors = [
+            ("colorSeries", self.series_color),
+            ("colorNegative", self.negative_color),
+            ("colorAxis", (0, 0, 0)),
+            ("colorMarkers", self.marker_color),
+            ("colorFirst", self.marker_color),
+            ("colorLast", self.marker_color),
+            ("colorHigh", self.marker_color),
+            ("colorLow", self.marker_color),
+        ];
+        for (name, (r, g, b)) in colors.iter() {
+            writeln!(writer, "<x14:{} rgb=\"FF{:02X}{:02X}{:02X}\"/>", name, r, g, b)?;
+        }
+        writeln!(writer, "<x14:sparklines>")?;
+        let sheet_name = escape_xml(&quote_sheet_name(sheet_name));
+        let first_column = column_letter(first_column);
+        let last_column = column_letter(last_column);
+        let location_column = column_letter(location_column);
+        for row in rows.iter().flat_map(|(first, last)| *first..=*last) {
+            writeln!(
+                writer,
+                "<x14:sparkline><xm:f>{}!{}{}:{}{}</xm:f><xm:sqref>{}{}</xm:sqref></x14:sparkline>",
+                sheet_name, first_column, row, last_column, row, location_column, row
+            )?;
+        }
+        writeln!(writer, "</x14:sparklines>\n</x14:sparklineGroup>")
+    }
+}
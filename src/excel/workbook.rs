@@ -1,17 +1,74 @@
-use crate::excel::Sheet;
-use std::io::{Result as IoResult, Seek, Write};
-use zip::{write::FileOptions, ZipWriter};
+use crate::excel::defined_name::DefinedNames;
+use crate::excel::doc_properties::CustomProperties;
+use crate::excel::encryption::{check_package_size, write_encrypted_package};
+use crate::excel::package::{Entry, TemporaryFile, MAX_COMPRESSION_LEVEL};
+use crate::excel::row::escape_xml;
+use crate::excel::sheet::SheetParts;
+use crate::excel::{
+    CellProtection, CompressionMethod, CustomPropertyValue, DocProperties, PackageWriter,
+    ParallelSheet, Sheet, WorkbookProtection,
+};
+use std::cell::RefCell;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+/// The id of the built-in hyperlink style, used by hyperlink cells without an explicit style.
+pub(crate) const HYPERLINK_STYLE_ID: usize = 1;
 
 /// The main struct to create a XLSX document. It is important to always [finish](WorkBook::finish) a workbook or the XLSX file will not be valid.
 pub struct WorkBook<W>
 where
-    W: Write + Seek,
+    W: Write,
 {
-    number_of_sheets: usize,
+    sheets: Vec<Rc<RefCell<SheetParts>>>,
     fills: Vec<Fill>,
     fonts: Vec<Font>,
     styles: Vec<CellStyle>,
-    zip_writer: ZipWriter<W>,
+    defined_names: DefinedNames,
+    protection: Option<WorkbookProtection>,
+    properties: DocProperties,
+    custom_properties: CustomProperties,
+    /// Where the package starts in a seekable writer, to read it back when it is encrypted.
+    package_start: Option<u64>,
+    /// The number of sheets and styles declared when the metadata was [written first](WorkBook::write_metadata_first).
+    declared: Option<(usize, usize)>,
+    zip_writer: PackageWriter<W>,
+}
+
+/// A sheet written on a worker thread, waiting to be copied into the workbook.
+struct ParallelSheetFile {
+    parts: SheetParts,
+    temporary: TemporaryFile,
+    entries: Vec<Entry>,
+}
+
+impl ParallelSheetFile {
+    fn write(
+        id: usize,
+        method: CompressionMethod,
+        level: u32,
+        function: impl FnOnce(ParallelSheet<'_>) -> IoResult<()>,
+    ) -> IoResult<Self> {
+        let temporary = TemporaryFile::new()?;
+        let mut package = PackageWriter::new(BufWriter::new(temporary.file.try_clone()?))?;
+        package.set_compression_method(method);
+        package.set_compression_level(level);
+        let parts = Rc::new(RefCell::new(SheetParts::new(id)));
+        function(Sheet::new(id, &mut package, parts.clone()))?;
+        let (writer, entries) = package.finish_parts()?;
+        writer.into_inner().map_err(|err| err.into_error())?;
+        if entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the sheet {} was not written", id),
+            ));
+        }
+        let parts = Rc::try_unwrap(parts)
+            .map_err(|_| Error::other(format!("the sheet {} is still being written", id)))?
+            .into_inner();
+        Ok(Self { parts, temporary, entries })
+    }
 }
 
 struct Font {
@@ -22,18 +79,30 @@ struct Fill {
     foreground_rgb: (u8, u8, u8),
 }
 
-/// The cell style. Right now we can only set foreground color and background color.
+/// The cell style. Right now we can only set foreground color, background color and the protection of the cell.
 #[derive(Clone, Debug)]
 pub struct CellStyle {
     id: usize,
     fill_id: usize,
     font_id: usize,
+    locked: bool,
+    hidden: bool,
 }
 
 impl CellStyle {
     pub fn get_id(&self) -> usize {
         self.id
     }
+
+    /// Whether the cells can't be edited when the sheet is protected. Cells are locked by default.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Whether the formulas of the cells are hidden when the sheet is protected.
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
 }
 
 impl<W> WorkBook<W>
@@ -41,45 +110,274 @@ where
     W: Write + Seek,
 {
     /// Creates a new WorkBook using the provider writer as output.
-    pub fn new(writer: W) -> IoResult<Self> {
-        Ok(WorkBook {
-            number_of_sheets: 0,
+    pub fn new(mut writer: W) -> IoResult<Self> {
+        let package_start = writer.stream_position()?;
+        Ok(Self::with_package(PackageWriter::new(writer)?, Some(package_start)))
+    }
+}
+
+impl<W> WorkBook<W>
+where
+    W: Write,
+{
+    /// Creates a new WorkBook writing into an output that can't seek, like the standard output, a pipe or an HTTP response body.
+    /// The sizes of every part are written after it (as ZIP data descriptors), so nothing is written twice.
+    pub fn new_streaming(writer: W) -> Self {
+        Self::with_package(PackageWriter::new_streaming(writer), None)
+    }
+
+    fn with_package(zip_writer: PackageWriter<W>, package_start: Option<u64>) -> Self {
+        WorkBook {
+            sheets: Vec::new(),
             fills: Vec::new(),
             styles: Vec::new(),
             fonts: Vec::new(),
-            zip_writer: ZipWriter::new(writer),
-        })
+            defined_names: DefinedNames::default(),
+            protection: None,
+            properties: DocProperties::default(),
+            custom_properties: CustomProperties::default(),
+            package_start,
+            declared: None,
+            zip_writer,
+        }
     }
 
     /// Create a neww sheet in the workbook.
     pub fn get_new_sheet(&mut self) -> Sheet<'_, W> {
-        self.number_of_sheets += 1;
-        Sheet::new(self.number_of_sheets, &mut self.zip_writer)
+        let parts = Rc::new(RefCell::new(SheetParts::new(self.sheets.len() + 1)));
+        self.sheets.push(parts.clone());
+        Sheet::new(self.sheets.len(), &mut self.zip_writer, parts)
+    }
+
+    /// Writes several sheets at the same time, one on each worker thread, up to the number of CPUs. They are added after the sheets already in the workbook, in the order of the functions.
+    /// Every function receives its [ParallelSheet](ParallelSheet), to set it and write it like any other sheet. It is compressed into a temporary file, then copied as it is into the workbook once all the sheets are written.
+    /// As the sheet is compressed like the others, its `<dimension>` is the one declared with [set_dimension](Sheet::set_dimension), unless it is stored.
+    ///
+    /// To give functions of different types, box them as `Box<dyn FnOnce(ParallelSheet) -> IoResult<()> + Send>`.
+    pub fn write_sheets_in_parallel<F>(&mut self, functions: Vec<F>) -> IoResult<()>
+    where
+        F: FnOnce(ParallelSheet<'_>) -> IoResult<()> + Send,
+    {
+        let first_id = self.sheets.len() + 1;
+        let (method, level) = self.zip_writer.compression();
+        let threads = std::thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1)
+            .min(functions.len());
+        let jobs = Mutex::new(functions.into_iter().enumerate());
+        let mut sheets = std::thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut sheets = Vec::new();
+                        loop {
+                            let job = jobs.lock().unwrap().next();
+                            match job {
+                                Some((i, function)) => sheets.push((
+                                    i,
+                                    ParallelSheetFile::write(first_id + i, method, level, function),
+                                )),
+                                None => return sheets,
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Vec<_>>()
+        });
+        sheets.sort_by_key(|(i, _)| *i);
+        for (_, sheet) in sheets {
+            let mut sheet = sheet?;
+            for entry in sheet.entries.iter() {
+                sheet.temporary.file.seek(SeekFrom::Start(entry.data_offset()))?;
+                self.zip_writer
+                    .write_compressed_part(entry, &mut BufReader::new(&mut sheet.temporary.file))?;
+            }
+            self.sheets.push(Rc::new(RefCell::new(sheet.parts)));
+        }
+        Ok(())
     }
 
     /// Finish the XLSX file. You need to call this so you can have a valid XLSX file.
     pub fn finish(mut self) -> IoResult<()> {
-        let options = FileOptions::default()
-            .large_file(true);
-        
-        self.write_content_type(&options)?;
-        self.write_rels(&options)?;
-        self.write_doc_props(&options)?;
-        self.write_styles(&options)?;
-        self.write_shared_strings(&options)?;
-        self.write_work_book(&options)?;
-        self.write_calc_chain(&options)?;
-        self.write_xl_rels(&options)?;
-        self.write_theme(&options)?;
+        self.write_package()?;
         self.zip_writer.finish()?;
         Ok(())
     }
 
+    /// Like [finish](WorkBook::finish), giving back the writer.
+    pub fn finish_into_inner(mut self) -> IoResult<W> {
+        self.write_package()?;
+        self.zip_writer.finish()
+    }
+
+    /// Like [finish](WorkBook::finish), but encrypting the XLSX file with a password, so Excel asks for it to open the file.
+    /// The finished package is read back from the writer and replaced by its encrypted version (ECMA-376 Agile Encryption, with AES-256 and SHA-512), so the writer also needs to be readable and the workbook created with [new](WorkBook::new).
+    /// The package is moved into a temporary file and encrypted from there one segment at a time, so it is never loaded in memory.
+    /// An encrypted package can't be bigger than 2 GiB. When it is, this fails before encrypting it, leaving the package unencrypted in the writer.
+    pub fn finish_with_password(mut self, password: &str) -> IoResult<()>
+    where
+        W: Read + Seek,
+    {
+        if password.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "the password can't be empty"));
+        }
+        let package_start = self.package_start.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "a streamed workbook can't be read back to encrypt it",
+            )
+        })?;
+        self.write_package()?;
+        let mut writer = self.zip_writer.finish()?;
+        let package_size = writer.stream_position()? - package_start;
+        check_package_size(package_size)?;
+        // The package is moved into a temporary file, as it is replaced by its encrypted version in the writer.
+        let mut temporary = TemporaryFile::new()?;
+        writer.seek(SeekFrom::Start(package_start))?;
+        let copied = std::io::copy(&mut (&mut writer).take(package_size), &mut temporary.file)?;
+        if copied != package_size {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the package can't be read back"));
+        }
+        temporary.file.seek(SeekFrom::Start(0))?;
+        writer.seek(SeekFrom::Start(package_start))?;
+        let mut output = BufWriter::new(&mut writer);
+        write_encrypted_package(&mut output, &mut BufReader::new(&temporary.file), package_size, password)?;
+        output.into_inner().map_err(|err| err.into_error())?.flush()
+    }
+
+    /// Writes the parts describing the workbook, like `[Content_Types].xml`, `workbook.xml` and `styles.xml`, before its sheets, so forward-only readers, like Java SAX readers or a streaming unzip in a browser, know the sheet names and styles when they reach the sheets, without buffering them.
+    /// It needs to be called before any sheet is written, with the number of sheets the workbook will have, as their names are declared here. The styles, the properties, the defined names and the protection of the workbook need to be set before too.
+    ///
+    /// The sheets can't have comments, images or charts, nor a print area or print titles, as their parts are declared in the metadata. [finish](WorkBook::finish) fails if the sheets or the styles don't match what was declared.
+    pub fn write_metadata_first(&mut self, sheets: usize) -> IoResult<()> {
+        if self.declared.is_some() || !self.sheets.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the metadata must be written before any sheet, and only once",
+            ));
+        }
+        if sheets == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the workbook needs at least 1 sheet",
+            ));
+        }
+        self.sheets = (1..=sheets)
+            .map(|id| Rc::new(RefCell::new(SheetParts::new(id))))
+            .collect();
+        let written = self.write_metadata();
+        self.sheets.clear();
+        written?;
+        self.declared = Some((sheets, self.styles.len()));
+        Ok(())
+    }
+
+    fn write_metadata(&mut self) -> IoResult<()> {
+        self.write_content_type()?;
+        self.write_rels()?;
+        self.write_doc_props()?;
+        self.write_work_book()?;
+        self.write_xl_rels()?;
+        self.write_styles()?;
+        self.write_shared_strings()?;
+        self.write_theme()?;
+        self.write_calc_chain()
+    }
+
+    /// Checks the sheets and the styles match the metadata written first, as it can't be written again.
+    fn check_declared(&self, sheets: usize, styles: usize) -> IoResult<()> {
+        if self.sheets.len() != sheets {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the metadata declared {} sheets, but {} were written",
+                    sheets,
+                    self.sheets.len()
+                ),
+            ));
+        }
+        if self.styles.len() != styles {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the styles must be created before the metadata is written",
+            ));
+        }
+        for parts in self.sheets.iter() {
+            let parts = parts.borrow();
+            if !parts.comments.is_empty() || !parts.drawing.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the sheet {} can't have comments, images or charts, as the metadata was written first",
+                        parts.id
+                    ),
+                ));
+            }
+            if !parts.defined_names.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the print area and titles of the sheet {} must be defined with define_local_name before the metadata is written",
+                        parts.id
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_package(&mut self) -> IoResult<()> {
+        if let Some((sheets, styles)) = self.declared {
+            self.check_declared(sheets, styles)?;
+            return self.write_sheet_rels();
+        }
+        for parts in self.sheets.iter() {
+            let parts = parts.borrow();
+            for (name, formula) in parts.defined_names.iter() {
+                self.defined_names.add(Some(&parts.name()), name, formula)?;
+            }
+        }
+        self.write_content_type()?;
+        self.write_rels()?;
+        self.write_sheet_rels()?;
+        self.write_comments()?;
+        self.write_drawings()?;
+        self.write_doc_props()?;
+        self.write_styles()?;
+        self.write_shared_strings()?;
+        self.write_work_book()?;
+        self.write_calc_chain()?;
+        self.write_xl_rels()?;
+        self.write_theme()
+    }
+
     /// Create a new CellStyle to be used in this workbook using the provided rgb foreground and background colors.
     pub fn create_cell_style(
         &mut self,
         font_color_rgb: (u8, u8, u8),
         background_color_rgb: (u8, u8, u8),
+    ) -> CellStyle {
+        self.create_cell_style_with_protection(
+            font_color_rgb,
+            background_color_rgb,
+            CellProtection::default(),
+        )
+    }
+
+    /// Like [create_cell_style](WorkBook::create_cell_style), also setting whether the cells are locked and their formulas hidden when the sheet is [protected](crate::excel::Sheet::set_protection).
+    pub fn create_cell_style_with_protection(
+        &mut self,
+        font_color_rgb: (u8, u8, u8),
+        background_color_rgb: (u8, u8, u8),
+        protection: CellProtection,
     ) -> CellStyle {
         self.fills.push(Fill {
             foreground_rgb: background_color_rgb,
@@ -88,17 +386,103 @@ where
             color: font_color_rgb,
         });
         let style = CellStyle {
-            id: self.styles.len() + 1,
+            id: self.styles.len() + 2,
             fill_id: self.fills.len() + 1,
-            font_id: self.fonts.len(),
+            font_id: self.fonts.len() + 1,
+            locked: protection.locked,
+            hidden: protection.hidden,
         };
         self.styles.push(style.clone());
         style
     }
 
-    fn write_content_type(&mut self, options: &FileOptions) -> IoResult<()> {
+    /// Defines a name visible in the whole workbook, like `TaxRate` for `0.21` or `DataRange` for `'Sheet 1'!$A$1:$D$100`.
+    pub fn define_name(&mut self, name: &str, formula: &str) -> IoResult<()> {
+        self.check_metadata_not_written()?;
+        self.defined_names.add(None, name, formula)
+    }
+
+    /// Defines a name visible only in the sheet with the given name. This is also how the built-in `_xlnm.Print_Area`, `_xlnm.Print_Titles` and `_xlnm._FilterDatabase` names are defined.
+    pub fn define_local_name(&mut self, sheet_name: &str, name: &str, formula: &str) -> IoResult<()> {
+        self.check_metadata_not_written()?;
+        self.defined_names.add(Some(sheet_name), name, formula)
+    }
+
+    /// Sets the title, the author and the other properties of the document.
+    pub fn set_properties(&mut self, properties: DocProperties) -> IoResult<()> {
+        self.check_metadata_not_written()?;
+        self.properties = properties;
+        Ok(())
+    }
+
+    /// Sets a custom property of the document, like `Classification` with `"Confidential"`. The value can be a text, a number, a bool or a date.
+    pub fn set_custom_property(&mut self, name: &str, value: impl Into<CustomPropertyValue>) -> IoResult<()> {
+        self.check_metadata_not_written()?;
+        self.custom_properties.set(name, value.into())
+    }
+
+    fn check_metadata_not_written(&self) -> IoResult<()> {
+        if self.declared.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the metadata of the workbook was already written",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets how the parts of the workbook are compressed, including the sheets written after this. They are deflated by default.
+    /// Some forward-only readers can't read stored parts of a [streamed](WorkBook::new_streaming) workbook, as their size is only written after them.
+    pub fn set_compression_method(&mut self, method: CompressionMethod) {
+        self.zip_writer.set_compression_method(method);
+    }
+
+    /// Sets the deflate level, from 0 (the fastest) to 9 (the smallest files). It is 6 by default.
+    pub fn set_compression_level(&mut self, level: u32) -> IoResult<()> {
+        if level > MAX_COMPRESSION_LEVEL {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the compression level can't be greater than {}", MAX_COMPRESSION_LEVEL),
+            ));
+        }
+        self.zip_writer.set_compression_level(level);
+        Ok(())
+    }
+
+    /// Deflates every part with several threads, splitting it in blocks compressed apart and joined into a single deflate stream, like pigz, so a huge sheet uses all the cores.
+    /// The files are slightly bigger, as the blocks don't share their history. Only a couple of blocks per thread are kept in memory. It is 1 by default.
+    pub fn set_compression_threads(&mut self, threads: usize) -> IoResult<()> {
+        if threads == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the compression needs at least 1 thread",
+            ));
+        }
+        self.zip_writer.set_compression_threads(threads);
+        Ok(())
+    }
+
+    /// Protects the structure of the workbook, so users can't add, delete, rename or reorder its sheets.
+    pub fn set_protection(&mut self, protection: WorkbookProtection) -> IoResult<()> {
+        self.check_metadata_not_written()?;
+        self.protection = Some(protection);
+        Ok(())
+    }
+
+    /// The built-in hyperlink style (blue and underlined). Hyperlink cells without a style already use it.
+    pub fn hyperlink_style(&self) -> CellStyle {
+        CellStyle {
+            id: HYPERLINK_STYLE_ID,
+            fill_id: 0,
+            font_id: 1,
+            locked: true,
+            hidden: false,
+        }
+    }
+
+    fn write_content_type(&mut self) -> IoResult<()> {
         self.zip_writer
-            .start_file("[Content_Types].xml", *options)?;
+            .start_file("[Content_Types].xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -107,11 +491,37 @@ where
             <Default Extension="xml" ContentType="application/xml"/>
             <Default Extension="bin" ContentType="application/vnd.ms-excel.sheet.binary.macroEnabled.main"/>
             <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+            <Default Extension="vml" ContentType="application/vnd.openxmlformats-officedocument.vmlDrawing"/>
             <Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
                       "#
         )?;
-        for i in 0..self.number_of_sheets {
-            write!(self.zip_writer, "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>\n", i + 1)?;
+        let mut image_formats = Vec::new();
+        for parts in self.sheets.iter() {
+            for media in parts.borrow().drawing.media() {
+                if !image_formats.contains(&media.image.format()) {
+                    image_formats.push(media.image.format());
+                }
+            }
+        }
+        for format in image_formats {
+            writeln!(
+                self.zip_writer,
+                "<Default Extension=\"{}\" ContentType=\"{}\"/>",
+                format.extension(),
+                format.content_type()
+            )?;
+        }
+        for (i, parts) in self.sheets.iter().enumerate() {
+            writeln!(self.zip_writer, "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>", i + 1)?;
+            if !parts.borrow().comments.is_empty() {
+                writeln!(self.zip_writer, "<Override PartName=\"/xl/comments{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.comments+xml\"/>", i + 1)?;
+            }
+            if !parts.borrow().drawing.is_empty() {
+                writeln!(self.zip_writer, "<Override PartName=\"/xl/drawings/drawing{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.drawing+xml\"/>", i + 1)?;
+            }
+            for chart in parts.borrow().drawing.charts() {
+                writeln!(self.zip_writer, "<Override PartName=\"/xl/charts/{}\" ContentType=\"application/vnd.openxmlformats-officedocument.drawingml.chart+xml\"/>", chart.name)?;
+            }
         }
         write!(
             self.zip_writer,
@@ -121,13 +531,16 @@ where
     <Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>
     <Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
     <Override PartName="/docProps/app.xml" ContentType="application/vnd.openxmlformats-officedocument.extended-properties+xml"/>
-</Types>
 "#
-        )
+        )?;
+        if !self.custom_properties.is_empty() {
+            writeln!(self.zip_writer, "    <Override PartName=\"/docProps/custom.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.custom-properties+xml\"/>")?;
+        }
+        writeln!(self.zip_writer, "</Types>")
     }
 
-    fn write_rels(&mut self, options: &FileOptions) -> IoResult<()> {
-        self.zip_writer.start_file("_rels/.rels", *options)?;
+    fn write_rels(&mut self) -> IoResult<()> {
+        self.zip_writer.start_file("_rels/.rels")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -135,47 +548,98 @@ where
         <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
         <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/extended-properties" Target="docProps/app.xml"/>
         <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>
-        </Relationships>"#
-        )
+"#
+        )?;
+        if !self.custom_properties.is_empty() {
+            writeln!(self.zip_writer, "        <Relationship Id=\"rId4\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/custom-properties\" Target=\"docProps/custom.xml\"/>")?;
+        }
+        write!(self.zip_writer, "        </Relationships>")
     }
 
-    fn write_doc_props(&mut self, options: &FileOptions) -> IoResult<()> {
-        self.zip_writer.start_file("docProps/app.xml", *options)?;
-        write!(
-            self.zip_writer,
-            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-        <Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties"
-                    xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes">
-            <Application>SheetJS</Application>
-            <HeadingPairs>
-                <vt:vector size="2" baseType="variant">
-                    <vt:variant>
-                        <vt:lpstr>Worksheets</vt:lpstr>
-                    </vt:variant>
-                    <vt:variant>
-                        <vt:i4>1</vt:i4>
-                    </vt:variant>
-                </vt:vector>
-            </HeadingPairs>
-            <TitlesOfParts>
-                <vt:vector size="1" baseType="lpstr">
-                <vt:lpstr>SheetJS</vt:lpstr>
-                </vt:vector>
-            </TitlesOfParts>
-        </Properties>"#
-        )?;
-        self.zip_writer.start_file("docProps/core.xml", *options)?;
-        write!(
-            self.zip_writer,
-            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-        <cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"
-                           xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/"
-                           xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"/>"#
-        )
+    fn write_sheet_rels(&mut self) -> IoResult<()> {
+        for (i, parts) in self.sheets.iter().enumerate() {
+            let parts = parts.borrow();
+            if parts.relationships.is_empty() {
+                continue;
+            }
+            self.zip_writer
+                .start_file(format!("xl/worksheets/_rels/sheet{}.xml.rels", i + 1))?;
+            parts.relationships.write(&mut self.zip_writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_comments(&mut self) -> IoResult<()> {
+        // The shape ids are allocated in blocks of 1024 across the sheets, starting with the block 1.
+        let mut next_block = 1;
+        for parts in self.sheets.iter() {
+            let parts = parts.borrow();
+            if parts.comments.is_empty() {
+                continue;
+            }
+            let first_block = next_block;
+            next_block += parts.comments.shape_blocks();
+            self.zip_writer
+                .start_file(format!("xl/comments{}.xml", parts.id))?;
+            parts.comments.write_comments(&mut self.zip_writer)?;
+            self.zip_writer
+                .start_file(format!("xl/drawings/vmlDrawing{}.vml", parts.id))?;
+            parts.comments.write_vml(first_block, &mut self.zip_writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_drawings(&mut self) -> IoResult<()> {
+        let sheet_names = self
+            .sheets
+            .iter()
+            .map(|parts| parts.borrow().name())
+            .collect::<Vec<String>>();
+        for parts in self.sheets.iter() {
+            let parts = parts.borrow();
+            if parts.drawing.is_empty() {
+                continue;
+            }
+            self.zip_writer
+                .start_file(format!("xl/drawings/drawing{}.xml", parts.id))?;
+            parts.drawing.write(&mut self.zip_writer)?;
+            self.zip_writer
+                .start_file(format!("xl/drawings/_rels/drawing{}.xml.rels", parts.id))?;
+            parts.drawing.relationships.write(&mut self.zip_writer)?;
+            for media in parts.drawing.media() {
+                self.zip_writer
+                    .start_file(format!("xl/media/{}", media.name))?;
+                media.image.copy_to(&mut self.zip_writer)?;
+            }
+            for chart in parts.drawing.charts() {
+                chart.chart.check_sheets(&sheet_names)?;
+                self.zip_writer
+                    .start_file(format!("xl/charts/{}", chart.name))?;
+                chart.chart.write(&mut self.zip_writer)?;
+            }
+        }
+        Ok(())
     }
 
-    fn write_styles(&mut self, options: &FileOptions) -> IoResult<()> {
-        self.zip_writer.start_file("xl/styles.xml", *options)?;
+    fn write_doc_props(&mut self) -> IoResult<()> {
+        let sheet_names = self
+            .sheets
+            .iter()
+            .map(|parts| parts.borrow().name())
+            .collect::<Vec<String>>();
+        self.zip_writer.start_file("docProps/app.xml")?;
+        self.properties.write_app(&mut self.zip_writer, &sheet_names)?;
+        self.zip_writer.start_file("docProps/core.xml")?;
+        self.properties.write_core(&mut self.zip_writer)?;
+        if !self.custom_properties.is_empty() {
+            self.zip_writer.start_file("docProps/custom.xml")?;
+            self.custom_properties.write(&mut self.zip_writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_styles(&mut self) -> IoResult<()> {
+        self.zip_writer.start_file("xl/styles.xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -188,8 +652,15 @@ where
                     <name val="Calibri"/>
                     <family val="2"/>
                     <scheme val="minor"/>
+                </font>
+                <font>
+                    <u/>
+                    <sz val="12"/>
+                    <color theme="10"/>
+                    <name val="Calibri"/>
+                    <family val="2"/>
                 </font>"#,
-            self.fonts.len() + 1
+            self.fonts.len() + 2
         )?;
         for font in self.fonts.iter() {
             write!(
@@ -244,26 +715,39 @@ where
                     <diagonal/>
                 </border>
             </borders>
-        <cellStyleXfs count="1">
+        <cellStyleXfs count="2">
             <xf numFmtId="0" fontId="0" fillId="0" borderId="0"/>
+            <xf numFmtId="0" fontId="1" fillId="0" borderId="0" applyNumberFormat="0" applyFill="0" applyBorder="0" applyAlignment="0" applyProtection="0"/>
         </cellStyleXfs>
         <cellXfs count="{}">
             <xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>
+            <xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="1"/>
                 "#,
-            self.styles.len() + 1
+            self.styles.len() + 2
         )?;
         for style in self.styles.iter() {
             write!(
                 self.zip_writer,
-                "<xf numFmtId=\"0\" fontId=\"{}\" fillId=\"{}\" borderId=\"0\" xfId=\"0\" applyFont=\"1\" applyFill=\"1\"/>",
+                "<xf numFmtId=\"0\" fontId=\"{}\" fillId=\"{}\" borderId=\"0\" xfId=\"0\" applyFont=\"1\" applyFill=\"1\"",
                 style.font_id,
                 style.fill_id
             )?;
+            if style.locked && !style.hidden {
+                write!(self.zip_writer, "/>")?;
+            } else {
+                write!(
+                    self.zip_writer,
+                    " applyProtection=\"1\"><protection locked=\"{}\" hidden=\"{}\"/></xf>",
+                    if style.locked { 1 } else { 0 },
+                    if style.hidden { 1 } else { 0 }
+                )?;
+            }
         }
         write!(
             self.zip_writer,
             r#"</cellXfs>
-        <cellStyles count="1">
+        <cellStyles count="2">
+            <cellStyle name="Hyperlink" xfId="1" builtinId="8"/>
             <cellStyle name="Normal" xfId="0" builtinId="0"/>
         </cellStyles>
         <dxfs count="0"/>
@@ -272,9 +756,9 @@ where
         )
     }
 
-    fn write_shared_strings(&mut self, options: &FileOptions) -> IoResult<()> {
+    fn write_shared_strings(&mut self) -> IoResult<()> {
         self.zip_writer
-            .start_file("xl/sharedStrings.xml", *options)?;
+            .start_file("xl/sharedStrings.xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -282,21 +766,24 @@ where
         )
     }
 
-    fn write_work_book(&mut self, options: &FileOptions) -> IoResult<()> {
-        self.zip_writer.start_file("xl/workbook.xml", *options)?;
+    fn write_work_book(&mut self) -> IoResult<()> {
+        self.zip_writer.start_file("xl/workbook.xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
         <workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
             <workbookPr date1904="false"/>
-            <sheets>
 "#
         )?;
-        for i in 0..self.number_of_sheets {
-            write!(
+        if let Some(protection) = &self.protection {
+            protection.write(&mut self.zip_writer)?;
+        }
+        writeln!(self.zip_writer, "            <sheets>")?;
+        for (i, parts) in self.sheets.iter().enumerate() {
+            writeln!(
                 self.zip_writer,
-                "<sheet name=\"Sheet {}\" sheetId=\"{}\" r:id=\"rId{}\"/>\n",
-                i + 1,
+                "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+                escape_xml(&parts.borrow().name()),
                 i + 1,
                 i + 3
             )?;
@@ -305,13 +792,21 @@ where
             self.zip_writer,
             r#"
         </sheets>
-    </workbook>
 "#
-        )
+        )?;
+        if !self.defined_names.is_empty() {
+            let sheet_names = self
+                .sheets
+                .iter()
+                .map(|parts| parts.borrow().name())
+                .collect::<Vec<String>>();
+            self.defined_names.write(&mut self.zip_writer, &sheet_names)?;
+        }
+        writeln!(self.zip_writer, "    </workbook>")
     }
 
-    fn write_calc_chain(&mut self, options: &FileOptions) -> IoResult<()> {
-        self.zip_writer.start_file("xl/calcChain.xml", *options)?;
+    fn write_calc_chain(&mut self) -> IoResult<()> {
+        self.zip_writer.start_file("xl/calcChain.xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -319,9 +814,9 @@ where
         )
     }
 
-    fn write_xl_rels(&mut self, options: &FileOptions) -> IoResult<()> {
+    fn write_xl_rels(&mut self) -> IoResult<()> {
         self.zip_writer
-            .start_file("xl/_rels/workbook.xml.rels", *options)?;
+            .start_file("xl/_rels/workbook.xml.rels")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
@@ -331,10 +826,10 @@ where
                 "#
         )?;
         let mut last_rid = 2;
-        for i in 0..self.number_of_sheets {
-            write!(
+        for i in 0..self.sheets.len() {
+            writeln!(
                 self.zip_writer,
-                "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet{}.xml\"/>\n", i + 3, i + 1
+                "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet{}.xml\"/>", i + 3, i + 1
             )?;
             last_rid = i + 3;
         }
@@ -347,9 +842,9 @@ where
         )
     }
 
-    fn write_theme(&mut self, options: &FileOptions) -> IoResult<()> {
+    fn write_theme(&mut self) -> IoResult<()> {
         self.zip_writer
-            .start_file("xl/theme/theme1.xml", *options)?;
+            .start_file("xl/theme/theme1.xml")?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
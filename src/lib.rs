@@ -1,14 +1,75 @@
-mod excel;
+pub mod excel;
 
-use std::{fs::{File}, io::{BufReader, BufRead, Write, BufWriter}, fmt::Display};
+use std::{fs::{File, OpenOptions}, io::{BufReader, BufRead, Write}, fmt::Display};
 use neon::{prelude::*, types::Deferred};
 // use simple_xlsx_writer::{WorkBook, Row as XLSRow, Cell};
-use excel::{WorkBook, Row as XLSRow, Cell};
-
-const BUFFER_CAPACITY: usize = 1_000_000;
+use excel::{WorkBook, Row as XLSRow, Cell, CompressionMethod};
 
 struct Row<'a> (pub Vec<&'a str>);
 
+/// The most threads `compressionThreads` can ask for, as each of them is spawned.
+const MAX_COMPRESSION_THREADS: f64 = 1024.0;
+
+/// The options of `CsvToExcel`, given as an optional object after the paths.
+#[derive(Default)]
+struct ConvertOptions {
+    /// Encrypts the XLSX file with this password.
+    password: Option<String>,
+    /// `stored` or `deflated`, as by default.
+    compression: Option<CompressionMethod>,
+    /// The deflate level, from 0 to 9.
+    compression_level: Option<u32>,
+    /// How many threads deflate the sheet.
+    compression_threads: Option<usize>,
+    /// Writes the metadata parts before the sheet, for forward-only readers.
+    metadata_first: bool,
+}
+
+impl ConvertOptions {
+    fn from_js<'a>(cx: &mut FunctionContext<'a>, options: Option<Handle<'a, JsValue>>) -> NeonResult<Self> {
+        let options = match options {
+            Some(options) if options.is_a::<JsObject, _>(cx) => options.downcast_or_throw::<JsObject, _>(cx)?,
+            _ => return Ok(Self::default()),
+        };
+        let password = match options.get_opt::<JsString, _, _>(cx, "password")? {
+            Some(password) => match password.value(cx) {
+                password if password.is_empty() => return cx.throw_error("the password can't be empty"),
+                password => Some(password),
+            },
+            None => None,
+        };
+        let compression = match options.get_opt::<JsString, _, _>(cx, "compression")? {
+            Some(compression) => match compression.value(cx).as_str() {
+                "stored" => Some(CompressionMethod::Stored),
+                "deflated" => Some(CompressionMethod::Deflated),
+                other => return cx.throw_error(format!("unknown compression {}, it must be stored or deflated", other)),
+            },
+            None => None,
+        };
+        let compression_level = match options.get_opt::<JsNumber, _, _>(cx, "compressionLevel")? {
+            Some(level) => match level.value(cx) {
+                level if (0.0..=9.0).contains(&level) && level.fract() == 0.0 => Some(level as u32),
+                level => return cx.throw_range_error(format!("invalid compression level {}, it must be an integer from 0 to 9", level)),
+            },
+            None => None,
+        };
+        let compression_threads = match options.get_opt::<JsNumber, _, _>(cx, "compressionThreads")? {
+            Some(threads) => match threads.value(cx) {
+                threads if (1.0..=MAX_COMPRESSION_THREADS).contains(&threads) && threads.fract() == 0.0 => Some(threads as usize),
+                threads => return cx.throw_range_error(format!(
+                    "invalid compression threads {}, it must be an integer from 1 to {}",
+                    threads, MAX_COMPRESSION_THREADS
+                )),
+            },
+            None => None,
+        };
+        let metadata_first = options
+            .get_opt::<JsBoolean, _, _>(cx, "metadataFirst")?
+            .is_some_and(|metadata_first| metadata_first.value(cx));
+        Ok(Self { password, compression, compression_level, compression_threads, metadata_first })
+    }
+}
+
 impl<'a> Display for Row<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let comb_str = self.0.join(",");
@@ -23,7 +84,7 @@ fn read_file_liner<F>(filepath: String, fn_operation: &mut F) -> Result<(), std:
     let reader = BufReader::new(&file);
 
     for line in reader.lines() {
-        let res_line = line.unwrap();
+        let res_line = line?;
         let cols = res_line.split(",").collect::<Vec<&str>>();
         let row = Row(cols);
         fn_operation(row)?;
@@ -37,49 +98,83 @@ fn read_file_liner<F>(filepath: String, fn_operation: &mut F) -> Result<(), std:
 fn csv_to_excel(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let csv_path = cx.argument::<JsString>(0)?.value(&mut cx);
     let xls_path = cx.argument::<JsString>(1)?.value(&mut cx);
+    let options = cx.argument_opt(2);
+    let options = ConvertOptions::from_js(&mut cx, options)?;
 
     let channel = cx.channel();
     let (defer, promise) = cx.promise();
 
     std::thread::spawn(move || {
-        write_xlsx(csv_path, xls_path, channel, defer);
+        write_xlsx(csv_path, xls_path, options, channel, defer);
     });
 
     Ok(promise)
 }
 
-fn write_xlsx(csv_path: String, xls_path: String, channel: Channel, defer: Deferred) {
-    // let mut xls_file = BufWriter::with_capacity(BUFFER_CAPACITY, File::create(xls_path).unwrap());
-    let mut xls_file = File::create(xls_path).unwrap();
-    let mut workbook = Box::new(WorkBook::new(&mut xls_file ))
-        .expect("unable to initiate excel workbook");
-    let worksheet = workbook.get_new_sheet();
-    
-    let write_result = worksheet.write_sheet(true, |writer| {
-        let mut operation = |row: Row| {
-            let mut xls_row = XLSRow::new();
-    
-            for col in row.0 {
-                xls_row.add_cell(Cell::from(col));
-            }
-    
-            writer.write_row(xls_row)
-        };
-        read_file_liner(csv_path, &mut operation)
-    });
-    
-    workbook.finish().unwrap();
-    xls_file.flush().unwrap();
+fn configure_workbook<W: Write>(workbook: &mut WorkBook<W>, options: &ConvertOptions) -> Result<(), std::io::Error> {
+    if let Some(compression) = options.compression {
+        workbook.set_compression_method(compression);
+    }
+    if let Some(level) = options.compression_level {
+        workbook.set_compression_level(level)?;
+    }
+    if let Some(threads) = options.compression_threads {
+        workbook.set_compression_threads(threads)?;
+    }
+    if options.metadata_first {
+        workbook.write_metadata_first(1)?;
+    }
+    Ok(())
+}
+
+fn write_xlsx(csv_path: String, xls_path: String, options: ConvertOptions, channel: Channel, defer: Deferred) {
+    let result = convert(csv_path, xls_path, &options);
 
     // let res = read_file_liner(csv_path, &mut operation);
     defer.settle_with(&channel, move |mut cx| {
-        match write_result {
+        match result {
             Ok(()) => Ok(cx.boolean(true)),
             Err(err) => cx.throw_error(err.to_string()),
         }
     });
 }
 
+fn convert(csv_path: String, xls_path: String, options: &ConvertOptions) -> Result<(), std::io::Error> {
+    // let mut xls_file = BufWriter::with_capacity(BUFFER_CAPACITY, File::create(xls_path).unwrap());
+    // The file is also read when encrypting, to replace the package with its encrypted version.
+    let mut xls_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(xls_path)?;
+    let mut workbook = WorkBook::new(&mut xls_file)?;
+    let write_result = configure_workbook(&mut workbook, options).and_then(|()| {
+        let worksheet = workbook.get_new_sheet();
+
+        worksheet.write_sheet(|writer| {
+            let mut operation = |row: Row| {
+                let mut xls_row = XLSRow::new();
+
+                for col in row.0 {
+                    xls_row.add_cell(Cell::from(col));
+                }
+
+                writer.write_row(xls_row)
+            };
+            read_file_liner(csv_path, &mut operation)
+        })
+    });
+
+    // The workbook is finished even when the rows failed, so the file is still a valid XLSX.
+    let finish_result = match &options.password {
+        Some(password) => workbook.finish_with_password(password),
+        None => workbook.finish(),
+    };
+    write_result.and(finish_result)?;
+    xls_file.flush()
+}
+
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("CsvToExcel", csv_to_excel)?;
@@ -60,3 +60,40 @@ test("Library is able to create and convert xlsx", async () => {
 
   expect(res).toEqual(true);
 });
+
+test("Library passes the conversion options to the binding", async () => {
+  const writer = new CsvFileWriter("./test/source-lib.csv", ["No", "Name"]);
+  await writer.write([1, "John"]);
+  await writer.close();
+
+  const compressed = await Converter.toXLSX("./test/source-lib.csv", "./test/result-lib-options.xlsx", {
+    compression: "deflated",
+    compressionLevel: 1,
+    compressionThreads: 2,
+    metadataFirst: true,
+  });
+  expect(compressed).toEqual(true);
+  expect(fs.readFileSync("./test/result-lib-options.xlsx").subarray(0, 4)).toEqual(Buffer.from("PK\x03\x04", "latin1"));
+
+  const encrypted = await Converter.toXLSX("./test/source-lib.csv", "./test/result-lib-options.xlsx", {
+    password: "secret",
+    compression: "stored",
+  });
+  expect(encrypted).toEqual(true);
+  // An encrypted workbook is an OLE compound file instead of a zip.
+  expect(fs.readFileSync("./test/result-lib-options.xlsx").subarray(0, 4)).toEqual(Buffer.from([0xd0, 0xcf, 0x11, 0xe0]));
+  fs.unlinkSync("./test/result-lib-options.xlsx");
+});
+
+test("Library rejects invalid conversion options", async () => {
+  const convert = (options) => Converter.toXLSX("./test/source-lib.csv", "./test/result-lib-invalid.xlsx", options);
+
+  await expect(convert({ compressionLevel: 12 })).rejects.toThrow(RangeError);
+  await expect(convert({ compressionLevel: 1.5 })).rejects.toThrow(RangeError);
+  await expect(convert({ compressionThreads: 0 })).rejects.toThrow(RangeError);
+  await expect(convert({ compression: "zstd" })).rejects.toThrow("unknown compression");
+  await expect(convert({ password: "" })).rejects.toThrow("the password can't be empty");
+  await expect(
+    Converter.toXLSX("./test/source-lib.csv", "./test/missing-directory/result.xlsx")
+  ).rejects.toThrow();
+});